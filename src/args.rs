@@ -1,6 +1,14 @@
+use std::path::Path;
+
+use anyhow::*;
 use clap::Clap;
 
-use crate::control::ScrollControlType;
+use crate::config;
+use crate::control::{ScrollConfig, ScrollControlType, SmoothPreset};
+use crate::tpmiddle::{
+    AxisLockParams, ClickAction, ModifierRules, PassthroughParams, PassthroughTrigger, StickyParams,
+};
+use crate::transport_agnostic::TransportPolicy;
 
 #[derive(Clap)]
 #[clap(version, about = "Tweak your TrackPoint Keyboard")]
@@ -16,8 +24,77 @@ pub struct Args {
     #[clap(long, default_value = "classic")]
     pub scroll: ScrollControlType,
 
+    /// Tuning of the smooth scrolling: gentle, default or snappy
+    #[clap(long)]
+    pub smooth_preset: Option<SmoothPreset>,
+
+    /// Invert the scroll direction like natural scrolling of touchpads
+    #[clap(long)]
+    pub natural_scrolling: bool,
+
+    /// Scroll horizontally with vertical scrolls, and vice versa
+    #[clap(long)]
+    pub swap_axes: bool,
+
+    /// Lock the scroll to the dominant axis while the middle button is held
+    #[clap(long)]
+    pub axis_lock: bool,
+
+    /// What a middle click emits: middle, back, forward, double-click or none
+    #[clap(long)]
+    pub click: Option<ClickAction>,
+
+    /// What holding the middle button without scrolling emits
+    #[clap(long)]
+    pub long_press: Option<ClickAction>,
+
+    /// What two middle clicks in a row emit. A middle click waits to see if another follows
+    #[clap(long)]
+    pub double_press: Option<ClickAction>,
+
+    /// Pass the middle button through and move the pointer while it is held, always or only
+    /// with ctrl, shift or alt held at the press
+    #[clap(long)]
+    pub passthrough: Option<PassthroughTrigger>,
+
+    /// Toggle the scrolling by a middle click instead of holding the button (Linux only)
+    #[clap(long)]
+    pub sticky: bool,
+
+    /// Zoom with ctrl, scroll horizontally with shift, and scroll slowly with alt held
+    #[clap(long)]
+    pub modifiers: bool,
+
+    /// Load the tuning of the scrolling from the file
+    #[clap(long)]
+    pub config: Option<String>,
+
+    /// auto, prefer-usb, usb-only or bt-only
+    #[clap(long, default_value = "auto")]
+    pub transport: TransportPolicy,
+
     #[clap(long)]
     pub log: Option<String>,
+
+    /// Load additional device models from the file
+    #[clap(long)]
+    pub devices: Option<String>,
+
+    #[clap(subcommand)]
+    pub command: Option<SubCommand>,
+}
+
+#[derive(Clap)]
+pub enum SubCommand {
+    /// Run a recorded trace through the --scroll controller and write the wheel events as CSV
+    Simulate {
+        /// CSV of `time_ms,event[,delta]`, where event is down, up, vertical, horizontal, diagonal,
+        /// motion or modifiers
+        trace: String,
+        /// Write to the file instead of stdout
+        #[clap(short, long)]
+        output: Option<String>,
+    },
 }
 
 impl Args {
@@ -29,4 +106,75 @@ impl Args {
             _ => None,
         }
     }
+
+    /// Reads `--config` for the `--scroll` controller.
+    pub fn scroll_config(&self) -> Result<ScrollConfig> {
+        const SECTIONS: &[&str] = &[
+            "smooth",
+            "kinetic",
+            "accel",
+            "vertical",
+            "horizontal",
+            "axis_lock",
+            "click",
+            "passthrough",
+            "sticky",
+            "modifiers",
+        ];
+
+        let mut scroll = match &self.config {
+            Some(path) => {
+                let path = Path::new(path);
+                let sections = config::load(path)?;
+                let read = || {
+                    for section in &sections {
+                        if !SECTIONS.contains(&section.name.as_str()) {
+                            bail!("line {}: unknown section", section.line);
+                        }
+                        if cfg!(windows) && section.name == "sticky" {
+                            // It needs to capture the pointer motion of the TrackPoint.
+                            bail!(
+                                "line {}: `[sticky]` is not supported on Windows",
+                                section.line
+                            );
+                        }
+                    }
+                    ScrollConfig::from_sections(self.scroll, self.smooth_preset, &sections)
+                };
+                read().with_context(|| format!("reading {:?}", path))?
+            }
+            None => ScrollConfig::from_sections(self.scroll, self.smooth_preset, &[])?,
+        };
+        if self.natural_scrolling {
+            scroll.axes.invert();
+        }
+        if self.swap_axes {
+            scroll.axes.swap();
+        }
+        if self.axis_lock && scroll.axis_lock.is_none() {
+            scroll.axis_lock = Some(AxisLockParams::default());
+        }
+        if self.sticky && scroll.sticky.is_none() {
+            scroll.sticky = Some(StickyParams::default());
+        }
+        if self.modifiers && scroll.modifier_rules.is_none() {
+            scroll.modifier_rules = Some(ModifierRules::default());
+        }
+        if let Some(action) = self.click {
+            scroll.click.action = action;
+        }
+        if self.long_press.is_some() {
+            scroll.click.long_press = self.long_press;
+        }
+        if self.double_press.is_some() {
+            scroll.click.double_press = self.double_press;
+        }
+        if let Some(trigger) = self.passthrough {
+            scroll.passthrough = Some(PassthroughParams {
+                trigger,
+                ..scroll.passthrough.unwrap_or_default()
+            });
+        }
+        Ok(scroll)
+    }
 }
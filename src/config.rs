@@ -0,0 +1,123 @@
+//! A minimal INI-like configuration format.
+//!
+//! ```text
+//! # Comment
+//! [section]
+//! key = value
+//! ```
+
+use std::convert::TryFrom;
+use std::fs;
+use std::path::Path;
+
+use anyhow::*;
+
+struct Entry {
+    key: String,
+    value: String,
+    line: usize,
+}
+
+pub struct Section {
+    pub name: String,
+    pub line: usize,
+    entries: Vec<Entry>,
+}
+
+impl Section {
+    /// Parses the value of `key` if it exists.
+    pub fn get<T>(&self, key: &str, parse: impl Fn(&str) -> Result<T>) -> Result<Option<T>> {
+        match self.entries.iter().rev().find(|entry| entry.key == key) {
+            Some(entry) => parse(&entry.value)
+                .map(Some)
+                .with_context(|| format!("line {}: invalid `{}`", entry.line, key)),
+            None => Ok(None),
+        }
+    }
+
+    pub fn require<T>(&self, key: &str, parse: impl Fn(&str) -> Result<T>) -> Result<T> {
+        self.get(key, parse)?
+            .ok_or_else(|| anyhow!("line {}: `[{}]` requires `{}`", self.line, self.name, key))
+    }
+
+    /// Rejects unknown keys to catch typos.
+    pub fn check_keys(&self, known: &[&str]) -> Result<()> {
+        for entry in &self.entries {
+            if !known.contains(&entry.key.as_str()) {
+                bail!(
+                    "line {}: unknown key `{}` in `[{}]`",
+                    entry.line,
+                    entry.key,
+                    self.name
+                );
+            }
+        }
+        Ok(())
+    }
+}
+
+pub fn parse(text: &str) -> Result<Vec<Section>> {
+    let mut sections: Vec<Section> = Vec::new();
+    for (index, line) in text.lines().enumerate() {
+        let line_number = index + 1;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        if let Some(name) = line.strip_prefix('[') {
+            let name = name
+                .strip_suffix(']')
+                .ok_or_else(|| anyhow!("line {}: unclosed section header", line_number))?;
+            sections.push(Section {
+                name: name.trim().to_owned(),
+                line: line_number,
+                entries: Vec::new(),
+            });
+            continue;
+        }
+
+        let mut parts = line.splitn(2, '=');
+        let key = parts.next().unwrap().trim();
+        let value = parts
+            .next()
+            .ok_or_else(|| anyhow!("line {}: expected `key = value`", line_number))?
+            .trim();
+        let section = sections
+            .last_mut()
+            .ok_or_else(|| anyhow!("line {}: `{}` is not in a section", line_number, key))?;
+        section.entries.push(Entry {
+            key: key.to_owned(),
+            value: value.to_owned(),
+            line: line_number,
+        });
+    }
+    Ok(sections)
+}
+
+pub fn load(path: &Path) -> Result<Vec<Section>> {
+    let text = fs::read_to_string(path).with_context(|| format!("reading {:?}", path))?;
+    parse(&text).with_context(|| format!("parsing {:?}", path))
+}
+
+/// Parses a hexadecimal integer with `0x` prefix, or a decimal one.
+pub fn parse_int<T: TryFrom<u64>>(value: &str) -> Result<T> {
+    let parsed = match value
+        .strip_prefix("0x")
+        .or_else(|| value.strip_prefix("0X"))
+    {
+        Some(hex) => u64::from_str_radix(hex, 16)?,
+        None => value.parse::<u64>()?,
+    };
+    T::try_from(parsed).map_err(|_| anyhow!("{} is out of range", value))
+}
+
+/// Parses a comma-separated list.
+pub fn parse_list<T>(value: &str, parse: impl Fn(&str) -> Result<T>) -> Result<Vec<T>> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+        .map(parse)
+        .collect()
+}
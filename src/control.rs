@@ -1,15 +1,49 @@
 use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
 
 use anyhow::*;
-use winapi::shared::minwindef::DWORD;
-use winapi::um::winuser::WHEEL_DELTA;
 
-use crate::input::send_wheel;
+use crate::config::Section;
+use crate::input::{Axis, ScrollDelta, WHEEL_DELTA};
+use crate::output::OutputSink;
+use crate::tpmiddle::{
+    AxisLockParams, ClickParams, ModifierRules, PassthroughParams, StickyParams, TPMiddle,
+    TPMiddleParams,
+};
+
+mod transform;
+
+pub use accel::{AccelController, AccelCurve, AccelParams, Accelerator};
+pub use kinetic::{Kinetic, KineticController, KineticParams};
+pub use smooth::{
+    Clock, ManualClock, SmoothController, SmoothParams, SmoothPreset, Smoother, SystemClock,
+    TickSource,
+};
+pub(crate) use transform::Transformer;
+pub use transform::{AxisTransform, AxisTransforms};
+
+/// A wheel event of `mouse_data` units along the axis.
+#[derive(Eq, PartialEq, Copy, Clone, Debug)]
+pub struct WheelTick {
+    pub axis: Axis,
+    pub mouse_data: i32,
+}
+
+/// The index of the axis in the arrays of vertical and horizontal.
+fn axis_index(axis: Axis) -> usize {
+    match axis {
+        Axis::Vertical => 0,
+        Axis::Horizontal => 1,
+    }
+}
 
 #[derive(Eq, PartialEq, Copy, Clone)]
 pub enum ScrollControlType {
     Classic,
     Smooth,
+    Kinetic,
+    Accel,
 }
 
 impl FromStr for ScrollControlType {
@@ -19,58 +53,468 @@ impl FromStr for ScrollControlType {
         match s {
             "classic" => Ok(ScrollControlType::Classic),
             "smooth" => Ok(ScrollControlType::Smooth),
+            "kinetic" => Ok(ScrollControlType::Kinetic),
+            "accel" => Ok(ScrollControlType::Accel),
             _ => Err(anyhow!("`{}` is an invalid type", s)),
         }
     }
 }
 
-impl ScrollControlType {
-    pub(crate) fn create_control(&self) -> Box<dyn ScrollControl> {
-        match self {
-            ScrollControlType::Classic => Box::new(classic::ClassicController),
-            ScrollControlType::Smooth => Box::new(smooth::SmoothController::new()),
+/// The scroll controller and its tuning.
+#[derive(Clone)]
+pub struct ScrollConfig {
+    pub control: ScrollControlType,
+    pub smooth: SmoothParams,
+    pub kinetic: KineticParams,
+    pub accel: AccelParams,
+    pub axes: AxisTransforms,
+    pub axis_lock: Option<AxisLockParams>,
+    pub click: ClickParams,
+    pub passthrough: Option<PassthroughParams>,
+    pub sticky: Option<StickyParams>,
+    pub modifier_rules: Option<ModifierRules>,
+}
+
+impl ScrollConfig {
+    /// Reads the tuning from the `[smooth]`, `[kinetic]`, `[accel]`, `[vertical]`,
+    /// `[horizontal]`, `[axis_lock]`, `[click]`, `[passthrough]`, `[sticky]` and `[modifiers]`
+    /// sections, if any. `[axis_lock]`, `[passthrough]`, `[sticky]` and `[modifiers]` enable them.
+    /// `smooth_preset` replaces the `preset` key of the `[smooth]` section.
+    pub fn from_sections(
+        control: ScrollControlType,
+        smooth_preset: Option<SmoothPreset>,
+        sections: &[Section],
+    ) -> Result<Self> {
+        let find = |name: &str| sections.iter().rev().find(|section| section.name == name);
+        let smooth = match find("smooth") {
+            Some(section) => SmoothParams::from_section(section, smooth_preset)?,
+            None => SmoothParams::preset(smooth_preset.unwrap_or(SmoothPreset::Default)),
+        };
+        let kinetic = match find("kinetic") {
+            Some(section) => KineticParams::from_section(section)?,
+            None => KineticParams::default(),
+        };
+        let accel = match find("accel") {
+            Some(section) => AccelParams::from_section(section)?,
+            None => AccelParams::default(),
+        };
+        Ok(Self {
+            control,
+            smooth,
+            kinetic,
+            accel,
+            axes: AxisTransforms::from_sections(sections)?,
+            axis_lock: find("axis_lock")
+                .map(AxisLockParams::from_section)
+                .transpose()?,
+            click: match find("click") {
+                Some(section) => ClickParams::from_section(section)?,
+                None => ClickParams::default(),
+            },
+            passthrough: find("passthrough")
+                .map(PassthroughParams::from_section)
+                .transpose()?,
+            sticky: find("sticky").map(StickyParams::from_section).transpose()?,
+            modifier_rules: find("modifiers")
+                .map(ModifierRules::from_section)
+                .transpose()?,
+        })
+    }
+
+    pub fn create_tpmiddle(&self) -> TPMiddle {
+        TPMiddle::with_params(TPMiddleParams {
+            axes: self.axes,
+            click: self.click,
+            axis_lock: self.axis_lock,
+            passthrough: self.passthrough,
+            sticky: self.sticky,
+            modifier_rules: self.modifier_rules,
+        })
+    }
+
+    pub fn create_control(&self, output: Arc<dyn OutputSink>) -> Box<dyn ScrollControl> {
+        match self.control {
+            ScrollControlType::Classic => Box::new(classic::ClassicController::new(output)),
+            ScrollControlType::Smooth => {
+                Box::new(smooth::SmoothController::new(self.smooth, output))
+            }
+            ScrollControlType::Kinetic => {
+                Box::new(kinetic::KineticController::new(self.kinetic, output))
+            }
+            ScrollControlType::Accel => {
+                Box::new(accel::AccelController::new(self.accel.clone(), output))
+            }
+        }
+    }
+
+    /// Creates the controller without a thread, driven by `clock` and [`StepControl::tick`].
+    pub fn create_stepper(&self, clock: impl Clock + 'static) -> Box<dyn StepControl> {
+        match self.control {
+            ScrollControlType::Classic => Box::new(classic::ClassicStepper),
+            ScrollControlType::Smooth => Box::new(Smoother::new(self.smooth, clock)),
+            ScrollControlType::Kinetic => Box::new(Kinetic::new(self.kinetic)),
+            ScrollControlType::Accel => Box::new(Accelerator::new(self.accel.clone(), clock)),
         }
     }
+
+    /// The interval of [`StepControl::tick`].
+    pub fn tick_interval(&self) -> Duration {
+        let wheel_tick_freq = match self.control {
+            ScrollControlType::Kinetic => kinetic::WHEEL_TICK_FREQ,
+            _ => self.smooth.wheel_tick_freq,
+        };
+        Duration::from_secs(1) / wheel_tick_freq
+    }
 }
 
 pub trait ScrollControl {
-    fn scroll(&self, event: DWORD, units: i8);
+    fn scroll(&self, delta: ScrollDelta);
+    /// The middle button is pressed.
+    fn press(&self);
+    /// The middle button is released. Momentum scrolling keeps coasting.
     fn stop(&self);
+    /// The scrolling ends without a release, like at the idle timeout of the sticky scroll. It
+    /// also stops the coasting.
+    fn halt(&self) {
+        self.stop();
+    }
+}
+
+/// The algorithm of a [`ScrollControl`], stepped by the caller to simulate it.
+pub trait StepControl {
+    /// Returns the wheel events emitted right away.
+    fn scroll(&mut self, delta: ScrollDelta) -> Vec<WheelTick>;
+    fn press(&mut self);
+    fn stop(&mut self);
+    fn halt(&mut self) {
+        self.stop();
+    }
+    /// Steps a tick of [`ScrollConfig::tick_interval`], and returns the wheel events of each axis.
+    /// Returns none when the controller needs no more ticks until the next scroll.
+    fn tick(&mut self) -> Vec<WheelTick>;
 }
 
 mod classic {
     use super::*;
 
-    pub struct ClassicController;
+    pub struct ClassicController {
+        output: Arc<dyn OutputSink>,
+    }
+
+    impl ClassicController {
+        pub fn new(output: Arc<dyn OutputSink>) -> Self {
+            Self { output }
+        }
+    }
 
     impl ScrollControl for ClassicController {
-        fn scroll(&self, event: DWORD, delta: i8) {
-            send_wheel(event, (delta as i32 * WHEEL_DELTA as i32) as _)
+        fn scroll(&self, delta: ScrollDelta) {
+            for (axis, delta) in delta.axes() {
+                self.output.send_wheel(axis, delta as i32 * WHEEL_DELTA)
+            }
         }
 
+        fn press(&self) {}
+
         fn stop(&self) {}
     }
+
+    pub struct ClassicStepper;
+
+    impl StepControl for ClassicStepper {
+        fn scroll(&mut self, delta: ScrollDelta) -> Vec<WheelTick> {
+            delta
+                .axes()
+                .map(|(axis, delta)| WheelTick {
+                    axis,
+                    mouse_data: delta as i32 * WHEEL_DELTA,
+                })
+                .collect()
+        }
+
+        fn press(&mut self) {}
+
+        fn stop(&mut self) {}
+
+        fn tick(&mut self) -> Vec<WheelTick> {
+            Vec::new()
+        }
+    }
 }
 
 mod smooth {
     use super::*;
 
+    use std::sync::{Arc, Mutex};
     use std::thread::{spawn, JoinHandle};
     use std::time::Instant;
 
     use crossbeam_channel::{bounded, Receiver, Sender, TryRecvError};
     use spin_sleep::LoopHelper;
 
-    // Empirically found min feed interval
-    const MIN_FEED_INTERVAL_SECS: f32 = 0.015;
-    // Treat feed intervals greater than this as a separate wheel event
-    const MAX_FEED_INTERVAL_SECS: f32 = 0.3;
+    use crate::config::parse_int;
 
-    const WHEEL_TICK_FREQ: u64 = 120;
-    const WHEEL_TICK_INTERVAL_SECS: f32 = 1.0 / WHEEL_TICK_FREQ as f32;
+    #[derive(Eq, PartialEq, Copy, Clone, Debug)]
+    pub enum SmoothPreset {
+        /// Glides longer after the scroll.
+        Gentle,
+        Default,
+        /// Follows the scroll closely and stops quickly.
+        Snappy,
+    }
+
+    impl FromStr for SmoothPreset {
+        type Err = anyhow::Error;
+
+        fn from_str(s: &str) -> Result<Self, Self::Err> {
+            match s {
+                "gentle" => Ok(SmoothPreset::Gentle),
+                "default" => Ok(SmoothPreset::Default),
+                "snappy" => Ok(SmoothPreset::Snappy),
+                _ => Err(anyhow!("`{}` is an invalid preset", s)),
+            }
+        }
+    }
+
+    /// Tuning of the smooth scrolling.
+    #[derive(PartialEq, Copy, Clone, Debug)]
+    pub struct SmoothParams {
+        /// Empirically found min feed interval.
+        pub min_feed_interval_secs: f32,
+        /// Treat feed intervals greater than this as a separate wheel event.
+        pub max_feed_interval_secs: f32,
+        pub wheel_tick_freq: u32,
+        /// Time to fully drain the buffer into the reservoir.
+        pub buffer_max_drain_duration_secs: f32,
+        /// Weight of the latest feed in the moving averages of the feed interval and amount.
+        pub moving_avg_coeff: f32,
+    }
+
+    impl SmoothParams {
+        pub fn preset(preset: SmoothPreset) -> Self {
+            let default = Self {
+                min_feed_interval_secs: 0.015,
+                max_feed_interval_secs: 0.3,
+                wheel_tick_freq: 120,
+                buffer_max_drain_duration_secs: 0.05,
+                moving_avg_coeff: 0.5,
+            };
+            match preset {
+                SmoothPreset::Gentle => Self {
+                    max_feed_interval_secs: 0.5,
+                    buffer_max_drain_duration_secs: 0.1,
+                    moving_avg_coeff: 0.3,
+                    ..default
+                },
+                SmoothPreset::Default => default,
+                SmoothPreset::Snappy => Self {
+                    max_feed_interval_secs: 0.2,
+                    buffer_max_drain_duration_secs: 0.03,
+                    moving_avg_coeff: 0.7,
+                    ..default
+                },
+            }
+        }
+
+        /// Reads a `[smooth]` section. Its keys override its `preset`, or `preset` if given.
+        pub(crate) fn from_section(
+            section: &Section,
+            preset: Option<SmoothPreset>,
+        ) -> Result<Self> {
+            section.check_keys(&[
+                "preset",
+                "min_feed_interval_secs",
+                "max_feed_interval_secs",
+                "wheel_tick_freq",
+                "buffer_max_drain_duration_secs",
+                "moving_avg_coeff",
+            ])?;
+            let parse_secs = |value: &str| Ok(value.parse::<f32>()?);
+
+            let preset = match preset {
+                Some(preset) => preset,
+                None => section
+                    .get("preset", str::parse)?
+                    .unwrap_or(SmoothPreset::Default),
+            };
+            let preset = Self::preset(preset);
+            let params = Self {
+                min_feed_interval_secs: section
+                    .get("min_feed_interval_secs", parse_secs)?
+                    .unwrap_or(preset.min_feed_interval_secs),
+                max_feed_interval_secs: section
+                    .get("max_feed_interval_secs", parse_secs)?
+                    .unwrap_or(preset.max_feed_interval_secs),
+                wheel_tick_freq: section
+                    .get("wheel_tick_freq", parse_int)?
+                    .unwrap_or(preset.wheel_tick_freq),
+                buffer_max_drain_duration_secs: section
+                    .get("buffer_max_drain_duration_secs", parse_secs)?
+                    .unwrap_or(preset.buffer_max_drain_duration_secs),
+                moving_avg_coeff: section
+                    .get("moving_avg_coeff", parse_secs)?
+                    .unwrap_or(preset.moving_avg_coeff),
+            };
+            params
+                .validate()
+                .with_context(|| format!("line {}: invalid `[{}]`", section.line, section.name))?;
+            Ok(params)
+        }
+
+        pub fn validate(&self) -> Result<()> {
+            let positive = |name: &str, value: f32| {
+                ensure!(
+                    value.is_finite() && value > 0.0,
+                    "`{}` should be positive",
+                    name
+                );
+                Ok(())
+            };
+            positive("min_feed_interval_secs", self.min_feed_interval_secs)?;
+            positive("max_feed_interval_secs", self.max_feed_interval_secs)?;
+            positive(
+                "buffer_max_drain_duration_secs",
+                self.buffer_max_drain_duration_secs,
+            )?;
+            ensure!(
+                self.min_feed_interval_secs < self.max_feed_interval_secs,
+                "`min_feed_interval_secs` should be less than `max_feed_interval_secs`"
+            );
+            ensure!(
+                (1..=1000).contains(&self.wheel_tick_freq),
+                "`wheel_tick_freq` should be in [1, 1000]"
+            );
+            ensure!(
+                self.moving_avg_coeff > 0.0 && self.moving_avg_coeff <= 1.0,
+                "`moving_avg_coeff` should be in (0, 1]"
+            );
+            Ok(())
+        }
+
+        fn wheel_tick_interval_secs(&self) -> f32 {
+            1.0 / self.wheel_tick_freq as f32
+        }
+    }
+
+    impl Default for SmoothParams {
+        fn default() -> Self {
+            Self::preset(SmoothPreset::Default)
+        }
+    }
+
+    /// The time when scroll events arrive.
+    pub trait Clock: Send {
+        fn now(&self) -> Instant;
+    }
+
+    pub struct SystemClock;
+
+    impl Clock for SystemClock {
+        fn now(&self) -> Instant {
+            Instant::now()
+        }
+    }
+
+    /// A clock that advances only when told to. Clones share the time.
+    #[derive(Clone)]
+    pub struct ManualClock {
+        now: Arc<Mutex<Instant>>,
+    }
+
+    impl ManualClock {
+        pub fn new() -> Self {
+            Self {
+                now: Arc::new(Mutex::new(Instant::now())),
+            }
+        }
+
+        pub fn advance(&self, duration: Duration) {
+            *self.now.lock().unwrap() += duration;
+        }
+    }
+
+    impl Default for ManualClock {
+        fn default() -> Self {
+            Self::new()
+        }
+    }
+
+    impl Clock for ManualClock {
+        fn now(&self) -> Instant {
+            *self.now.lock().unwrap()
+        }
+    }
+
+    /// Paces the wheel ticks of [`SmoothController`] at [`SmoothParams::wheel_tick_freq`].
+    pub trait TickSource: Send {
+        /// Receives a message on each tick between `resume` and `stop`.
+        fn receiver(&self) -> &Receiver<()>;
+        fn resume(&self);
+        fn stop(&self);
+    }
+
+    /// The smooth scrolling algorithm without a thread, stepped by the caller.
+    ///
+    /// Each axis scrolls on its own, so a diagonal scroll doesn't reset the other axis.
+    pub struct Smoother<C> {
+        params: SmoothParams,
+        clock: C,
+        /// Vertical and horizontal.
+        states: [State; 2],
+    }
+
+    impl<C: Clock> Smoother<C> {
+        pub fn new(params: SmoothParams, clock: C) -> Self {
+            Self {
+                params,
+                clock,
+                states: [State::Nop, State::Nop],
+            }
+        }
+
+        /// Feeds a scroll event at the current time of the clock.
+        /// Returns `true` when the ticks should resume.
+        pub fn scroll(&mut self, delta: ScrollDelta) -> bool {
+            let now = self.clock.now();
+            let mut resume = false;
+            for (axis, delta) in delta.axes() {
+                resume |= self.states[axis_index(axis)].feed(&self.params, now, delta);
+            }
+            resume
+        }
+
+        /// Steps a tick of `1 / wheel_tick_freq` seconds, and returns the wheel events of the
+        /// scrolling axes. Returns none when the scrolling is over, and the ticks should stop.
+        pub fn tick(&mut self) -> Vec<WheelTick> {
+            let params = &self.params;
+            [Axis::Vertical, Axis::Horizontal]
+                .iter()
+                .zip(self.states.iter_mut())
+                .filter_map(|(&axis, state)| state.tick(params, axis))
+                .collect()
+        }
+
+        pub fn stop(&mut self) {
+            self.states = [State::Nop, State::Nop];
+        }
+    }
+
+    impl<C: Clock> StepControl for Smoother<C> {
+        fn scroll(&mut self, delta: ScrollDelta) -> Vec<WheelTick> {
+            Smoother::scroll(self, delta);
+            Vec::new()
+        }
+
+        fn press(&mut self) {}
 
-    /// Time to fully drain the buffer into the reservoir.
-    const BUFFER_MAX_DRAIN_DURATION_SECS: f32 = 0.05;
+        fn stop(&mut self) {
+            Smoother::stop(self);
+        }
+
+        fn tick(&mut self) -> Vec<WheelTick> {
+            Smoother::tick(self)
+        }
+    }
 
     pub struct SmoothController {
         sender: Option<Sender<Event>>,
@@ -78,29 +522,39 @@ mod smooth {
     }
 
     impl SmoothController {
-        pub fn new() -> Self {
-            let ticker = Ticker::new(WHEEL_TICK_FREQ);
+        pub fn new(params: SmoothParams, output: Arc<dyn OutputSink>) -> Self {
+            let ticker = Ticker::new(params.wheel_tick_freq);
+            Self::with_sources(params, output, SystemClock, ticker)
+        }
+
+        pub fn with_sources(
+            params: SmoothParams,
+            output: Arc<dyn OutputSink>,
+            clock: impl Clock + 'static,
+            ticker: impl TickSource + 'static,
+        ) -> Self {
             let (sender, receiver) = bounded(1);
-            let mut state = State::Nop;
+            let mut smoother = Smoother::new(params, clock);
             let join_handle = spawn(move || loop {
                 crossbeam_channel::select! {
-                    recv(ticker.receiver) -> _ => {
-                        if let Some(wheel) = state.tick() {
-                            send_wheel(wheel.event, wheel.mouse_data);
-                        } else {
+                    recv(ticker.receiver()) -> _ => {
+                        let wheels = smoother.tick();
+                        if wheels.is_empty() {
                             ticker.stop();
                         }
+                        for wheel in wheels {
+                            output.send_wheel(wheel.axis, wheel.mouse_data);
+                        }
                     }
                     recv(receiver) -> event => {
                         match event {
-                            Ok(Event::Scroll { event, delta }) => {
-                                let now = Instant::now();
-                                if state.feed(now, event, delta) {
+                            Ok(Event::Scroll(delta)) => {
+                                if smoother.scroll(delta) {
                                     ticker.resume();
                                 }
                             }
                             Ok(Event::Stop) => {
-                                state = State::Nop;
+                                smoother.stop();
                                 ticker.stop();
                             }
                             Err(_) => {
@@ -118,13 +572,15 @@ mod smooth {
     }
 
     impl ScrollControl for SmoothController {
-        fn scroll(&self, event: DWORD, delta: i8) {
+        fn scroll(&self, delta: ScrollDelta) {
             let sender = self.sender.as_ref().unwrap();
             sender
-                .send(Event::Scroll { event, delta })
+                .send(Event::Scroll(delta))
                 .expect("Smooth scrolling thread is dead")
         }
 
+        fn press(&self) {}
+
         fn stop(&self) {
             let sender = self.sender.as_ref().unwrap();
             sender
@@ -142,7 +598,7 @@ mod smooth {
         }
     }
 
-    struct Ticker {
+    pub(super) struct Ticker {
         receiver: Receiver<()>,
         sender: Option<Sender<TickerCommand>>,
         join_handle: Option<JoinHandle<()>>,
@@ -154,7 +610,7 @@ mod smooth {
     }
 
     impl Ticker {
-        fn new(freq: u64) -> Self {
+        pub(super) fn new(freq: u32) -> Self {
             let (ticker_sender, ticker_receiver) = bounded(1);
             let (command_sender, command_receiver) = bounded(1);
             let join_handle = spawn(move || 'thread: loop {
@@ -185,6 +641,12 @@ mod smooth {
                 join_handle: Some(join_handle),
             }
         }
+    }
+
+    impl TickSource for Ticker {
+        fn receiver(&self) -> &Receiver<()> {
+            &self.receiver
+        }
 
         fn resume(&self) {
             let sender = self.sender.as_ref().unwrap();
@@ -211,14 +673,14 @@ mod smooth {
     }
 
     enum Event {
-        Scroll { event: DWORD, delta: i8 },
+        Scroll(ScrollDelta),
         Stop,
     }
 
+    /// The scrolling along an axis.
     #[derive(Debug)]
     enum State {
         Scrolling {
-            event: DWORD,
             scroll_direction: f32,
             buffer: f32,
             decay: Decay,
@@ -230,34 +692,33 @@ mod smooth {
     }
 
     impl State {
-        fn feed(&mut self, now: Instant, event: DWORD, delta: i8) -> bool {
+        fn feed(&mut self, params: &SmoothParams, now: Instant, delta: i8) -> bool {
             // Empirical feed pattern (number is `delta`)
             // slow scroll  : 1     1     1... >= 100ms interval, up to few seconds.
             // normal scroll: 1  1  1  1  1... <  100ms interval.
             // fast scroll  : 3333333333333... ~= 15ms interval, with greater `delta`
             match self {
                 State::Scrolling {
-                    event: prev_event,
                     scroll_direction,
                     buffer,
                     decay,
                     feed_rate,
                     ..
-                } if *prev_event == event && *scroll_direction as i8 == delta.signum() => {
-                    feed_rate.feed(now, delta.abs() as _);
+                } if *scroll_direction as i8 == delta.signum() => {
+                    feed_rate.feed(params, now, delta.abs() as _);
                     // To enable more precise wheel speed control, nudge the delta when the pressure is low,
                     // High pressure -> faster feed rate -> nudge ~ 1.0 (for a narrower range)
                     // Low pressure -> Slower feed rate -> nudge < 1.0 (for a broader range)
-                    let nudge = (MIN_FEED_INTERVAL_SECS / feed_rate.interval()).sqrt();
+                    let nudge = (params.min_feed_interval_secs / feed_rate.interval(params)).sqrt();
                     let value = delta.abs() as f32 * nudge;
                     *buffer += value;
                     *decay = Decay::AutomaticExponential;
                     false
                 }
                 _ => {
-                    let initial_nudge = (MIN_FEED_INTERVAL_SECS / MAX_FEED_INTERVAL_SECS).sqrt();
+                    let initial_nudge =
+                        (params.min_feed_interval_secs / params.max_feed_interval_secs).sqrt();
                     *self = State::Scrolling {
-                        event,
                         scroll_direction: delta.signum() as _,
                         buffer: delta.abs() as f32 * initial_nudge,
                         decay: Decay::AutomaticExponential,
@@ -270,10 +731,10 @@ mod smooth {
             }
         }
 
-        fn tick(&mut self) -> Option<WheelTick> {
+        fn tick(&mut self, params: &SmoothParams, axis: Axis) -> Option<WheelTick> {
+            let wheel_tick_freq = params.wheel_tick_freq as f32;
             match *self {
                 State::Scrolling {
-                    event,
                     scroll_direction,
                     ref mut buffer,
                     ref mut decay,
@@ -282,25 +743,25 @@ mod smooth {
                     ref feed_rate,
                     ..
                 } => {
-                    const BUFFER_MIN_DRAIN_PER_TICK: f32 =
-                        1.0 / BUFFER_MAX_DRAIN_DURATION_SECS / WHEEL_TICK_FREQ as f32;
+                    let buffer_min_drain_per_tick =
+                        1.0 / params.buffer_max_drain_duration_secs / wheel_tick_freq;
                     let drain = if *buffer > 1.0 {
                         // Greater buffer value, faster drain.
-                        *buffer * BUFFER_MIN_DRAIN_PER_TICK
+                        *buffer * buffer_min_drain_per_tick
                     } else {
                         // Use linear rate to eliminate long-tail
-                        BUFFER_MIN_DRAIN_PER_TICK.min(*buffer)
+                        buffer_min_drain_per_tick.min(*buffer)
                     };
                     *buffer -= drain;
                     *reservoir += drain;
                     if drain > 0.0 {
                         // Capping reservoir with `feed_rate` prevents `reservoir` grows indefinitely.
                         // `reservoir` might decay slower than the `feed_rate`.
-                        *reservoir = reservoir.min(feed_rate.moving_avg());
+                        *reservoir = reservoir.min(feed_rate.moving_avg(params));
                     }
 
-                    let feed_interval = feed_rate.interval();
-                    let decay_rate = WHEEL_TICK_INTERVAL_SECS / feed_interval;
+                    let feed_interval = feed_rate.interval(params);
+                    let decay_rate = params.wheel_tick_interval_secs() / feed_interval;
 
                     if *buffer == 0.0 && *decay == Decay::AutomaticExponential {
                         // The buffer is depleted. We assumes that the scrolling is stopped.
@@ -312,7 +773,7 @@ mod smooth {
                         *decay = Decay::Quadratic {
                             amount: *reservoir * decay_rate,
                             decreasing_rate: *reservoir * decay_rate
-                                / (feed_interval * 2.0 * WHEEL_TICK_FREQ as f32),
+                                / (feed_interval * 2.0 * wheel_tick_freq),
                         };
                     }
 
@@ -338,16 +799,16 @@ mod smooth {
                         delta += error.div_euclid(1.0) as i32;
                         *error = error.rem_euclid(1.0);
 
-                        delta as DWORD
+                        delta
                     };
 
                     if *reservoir == 0.0 || amount == 0.0 && *error < 1.0 {
                         *self = State::Nop;
                     }
 
-                    Some(WheelTick { event, mouse_data })
+                    Some(WheelTick { axis, mouse_data })
                 }
-                State::Nop { .. } => None,
+                State::Nop => None,
             }
         }
     }
@@ -358,11 +819,6 @@ mod smooth {
         AutomaticExponential,
     }
 
-    struct WheelTick {
-        event: DWORD,
-        mouse_data: DWORD,
-    }
-
     #[derive(Debug)]
     struct FeedRate {
         interval: Option<f32>,
@@ -379,18 +835,18 @@ mod smooth {
             }
         }
 
-        fn feed(&mut self, now: Instant, delta: f32) {
-            const MOVING_AVG_COEFF: f32 = 0.5;
+        fn feed(&mut self, params: &SmoothParams, now: Instant, delta: f32) {
+            let moving_avg_coeff = params.moving_avg_coeff;
 
             let diff = (now - self.prev).as_secs_f32();
             self.interval = if let Some(interval) = self.interval {
-                Some(interval * (1.0 - MOVING_AVG_COEFF) + diff * MOVING_AVG_COEFF)
+                Some(interval * (1.0 - moving_avg_coeff) + diff * moving_avg_coeff)
             } else {
                 Some(diff)
             };
 
             self.value = if let Some(value) = self.value {
-                Some(value * (1.0 - MOVING_AVG_COEFF) + delta * MOVING_AVG_COEFF)
+                Some(value * (1.0 - moving_avg_coeff) + delta * moving_avg_coeff)
             } else {
                 Some(delta)
             };
@@ -398,15 +854,858 @@ mod smooth {
             self.prev = now;
         }
 
-        fn interval(&self) -> f32 {
+        fn interval(&self, params: &SmoothParams) -> f32 {
             self.interval
-                .unwrap_or(MAX_FEED_INTERVAL_SECS)
-                .min(MAX_FEED_INTERVAL_SECS)
-                .max(MIN_FEED_INTERVAL_SECS)
+                .unwrap_or(params.max_feed_interval_secs)
+                .min(params.max_feed_interval_secs)
+                .max(params.min_feed_interval_secs)
+        }
+
+        fn moving_avg(&self, params: &SmoothParams) -> f32 {
+            self.value.unwrap_or(1.0) / self.interval(params)
+        }
+    }
+}
+
+mod kinetic {
+    use super::*;
+
+    use std::thread::{spawn, JoinHandle};
+
+    use crossbeam_channel::{bounded, Sender};
+
+    use super::smooth::Ticker;
+
+    pub const WHEEL_TICK_FREQ: u32 = 120;
+    const WHEEL_TICK_INTERVAL_SECS: f32 = 1.0 / WHEEL_TICK_FREQ as f32;
+
+    /// Coasting stops below this velocity in notches per second.
+    const MIN_VELOCITY: f32 = 0.5;
+    /// The velocity a scroll of a notch adds, in notches per second.
+    const NOTCH_VELOCITY: f32 = 4.0;
+
+    /// Tuning of the kinetic scrolling.
+    #[derive(PartialEq, Copy, Clone, Debug)]
+    pub struct KineticParams {
+        /// Rate of the exponential decay of the velocity per second.
+        /// A scroll of a notch coasts `4 / friction` notches in total.
+        pub friction: f32,
+        /// In notches per second.
+        pub max_velocity: f32,
+        /// Stop coasting when the middle button is pressed again.
+        pub stop_on_press: bool,
+    }
+
+    impl KineticParams {
+        pub(crate) fn from_section(section: &Section) -> Result<Self> {
+            section.check_keys(&["friction", "max_velocity", "stop_on_press"])?;
+            let parse_f32 = |value: &str| Ok(value.parse::<f32>()?);
+
+            let default = Self::default();
+            let params = Self {
+                friction: section
+                    .get("friction", parse_f32)?
+                    .unwrap_or(default.friction),
+                max_velocity: section
+                    .get("max_velocity", parse_f32)?
+                    .unwrap_or(default.max_velocity),
+                stop_on_press: section
+                    .get("stop_on_press", |value| Ok(value.parse::<bool>()?))?
+                    .unwrap_or(default.stop_on_press),
+            };
+            params
+                .validate()
+                .with_context(|| format!("line {}: invalid `[{}]`", section.line, section.name))?;
+            Ok(params)
         }
 
-        fn moving_avg(&self) -> f32 {
-            self.value.unwrap_or(1.0) / self.interval()
+        pub fn validate(&self) -> Result<()> {
+            ensure!(
+                self.friction.is_finite() && self.friction > 0.0,
+                "`friction` should be positive"
+            );
+            ensure!(
+                self.max_velocity.is_finite() && self.max_velocity > MIN_VELOCITY,
+                "`max_velocity` should be greater than {}",
+                MIN_VELOCITY
+            );
+            Ok(())
         }
     }
+
+    impl Default for KineticParams {
+        fn default() -> Self {
+            Self {
+                friction: 4.0,
+                max_velocity: 40.0,
+                stop_on_press: true,
+            }
+        }
+    }
+
+    #[derive(Debug)]
+    struct Motion {
+        /// Vertical and horizontal, in notches per second. `0` doesn't move along the axis.
+        velocity: [f32; 2],
+        error: [f32; 2],
+    }
+
+    /// Momentum scrolling. Scrolls add velocity, and friction decays it.
+    /// It keeps coasting after the middle button is released.
+    pub struct Kinetic {
+        params: KineticParams,
+        motion: Option<Motion>,
+    }
+
+    impl Kinetic {
+        pub fn new(params: KineticParams) -> Self {
+            Self {
+                params,
+                motion: None,
+            }
+        }
+
+        /// Returns `true` when the ticks should resume.
+        pub fn scroll(&mut self, delta: ScrollDelta) -> bool {
+            let max_velocity = self.params.max_velocity;
+            let started = self.motion.is_none();
+            let motion = self.motion.get_or_insert(Motion {
+                velocity: [0.0; 2],
+                error: [0.0; 2],
+            });
+            // A scroll against the motion brakes it, and one along another axis adds to it.
+            for (axis, delta) in delta.axes() {
+                let velocity = &mut motion.velocity[axis_index(axis)];
+                *velocity =
+                    (*velocity + delta as f32 * NOTCH_VELOCITY).clamp(-max_velocity, max_velocity);
+            }
+            started
+        }
+
+        pub fn press(&mut self) {
+            if self.params.stop_on_press {
+                self.motion = None;
+            }
+        }
+
+        pub fn halt(&mut self) {
+            self.motion = None;
+        }
+
+        /// Steps a tick of `1 / WHEEL_TICK_FREQ` seconds, and returns the wheel events of the
+        /// moving axes. Returns none when the coasting is over, and the ticks should stop.
+        pub fn tick(&mut self) -> Vec<WheelTick> {
+            let motion = match self.motion.as_mut() {
+                Some(motion) => motion,
+                None => return Vec::new(),
+            };
+
+            let mut wheels = Vec::new();
+            for &axis in &[Axis::Vertical, Axis::Horizontal] {
+                let velocity = &mut motion.velocity[axis_index(axis)];
+                let error = &mut motion.error[axis_index(axis)];
+                if *velocity == 0.0 {
+                    continue;
+                }
+
+                let delta_f32 = *velocity * WHEEL_TICK_INTERVAL_SECS * WHEEL_DELTA as f32;
+                let mut delta = delta_f32 as i32;
+                // accumulate f32 -> i32 rounding errors.
+                *error += delta_f32 - delta as f32;
+                delta += error.div_euclid(1.0) as i32;
+                *error = error.rem_euclid(1.0);
+
+                *velocity *= (-self.params.friction * WHEEL_TICK_INTERVAL_SECS).exp();
+                if velocity.abs() < MIN_VELOCITY {
+                    *velocity = 0.0;
+                }
+
+                wheels.push(WheelTick {
+                    axis,
+                    mouse_data: delta,
+                });
+            }
+            if motion.velocity == [0.0; 2] {
+                self.motion = None;
+            }
+            wheels
+        }
+    }
+
+    impl StepControl for Kinetic {
+        fn scroll(&mut self, delta: ScrollDelta) -> Vec<WheelTick> {
+            Kinetic::scroll(self, delta);
+            Vec::new()
+        }
+
+        fn press(&mut self) {
+            Kinetic::press(self);
+        }
+
+        fn stop(&mut self) {}
+
+        fn halt(&mut self) {
+            Kinetic::halt(self);
+        }
+
+        fn tick(&mut self) -> Vec<WheelTick> {
+            Kinetic::tick(self)
+        }
+    }
+
+    enum Event {
+        Scroll(ScrollDelta),
+        Press,
+        Halt,
+    }
+
+    pub struct KineticController {
+        sender: Option<Sender<Event>>,
+        join_handle: Option<JoinHandle<()>>,
+    }
+
+    impl KineticController {
+        pub fn new(params: KineticParams, output: Arc<dyn OutputSink>) -> Self {
+            let ticker = Ticker::new(WHEEL_TICK_FREQ);
+            let (sender, receiver) = bounded(1);
+            let mut kinetic = Kinetic::new(params);
+            let join_handle = spawn(move || loop {
+                crossbeam_channel::select! {
+                    recv(ticker.receiver()) -> _ => {
+                        let wheels = kinetic.tick();
+                        if wheels.is_empty() {
+                            ticker.stop();
+                        }
+                        for wheel in wheels {
+                            output.send_wheel(wheel.axis, wheel.mouse_data);
+                        }
+                    }
+                    recv(receiver) -> event => {
+                        match event {
+                            Ok(Event::Scroll(delta)) => {
+                                if kinetic.scroll(delta) {
+                                    ticker.resume();
+                                }
+                            }
+                            // The next tick stops the ticker if it no longer coasts.
+                            Ok(Event::Press) => kinetic.press(),
+                            Ok(Event::Halt) => kinetic.halt(),
+                            Err(_) => {
+                                break;
+                            }
+                        }
+                    }
+                };
+            });
+            Self {
+                sender: Some(sender),
+                join_handle: Some(join_handle),
+            }
+        }
+    }
+
+    impl ScrollControl for KineticController {
+        fn scroll(&self, delta: ScrollDelta) {
+            let sender = self.sender.as_ref().unwrap();
+            sender
+                .send(Event::Scroll(delta))
+                .expect("Kinetic scrolling thread is dead")
+        }
+
+        fn press(&self) {
+            let sender = self.sender.as_ref().unwrap();
+            sender
+                .send(Event::Press)
+                .expect("Kinetic scrolling thread is dead")
+        }
+
+        /// Keeps coasting.
+        fn stop(&self) {}
+
+        fn halt(&self) {
+            let sender = self.sender.as_ref().unwrap();
+            sender
+                .send(Event::Halt)
+                .expect("Kinetic scrolling thread is dead")
+        }
+    }
+
+    impl Drop for KineticController {
+        fn drop(&mut self) {
+            std::mem::drop(self.sender.take());
+            if let Some(join_handle) = self.join_handle.take() {
+                join_handle
+                    .join()
+                    .expect("Kinetic scrolling thread is dead");
+            }
+        }
+    }
+}
+
+mod accel {
+    use super::*;
+
+    use std::cell::RefCell;
+    use std::time::Instant;
+
+    use crate::config::parse_list;
+
+    const DEFAULT_EXPONENT: f32 = 1.5;
+    /// In notches per second. Between slow and normal scrolls.
+    const DEFAULT_REFERENCE_SPEED: f32 = 20.0;
+
+    /// Maps the input speed to the output speed, both in notches per second.
+    ///
+    /// The input speed is the `delta` of a scroll over the interval since the previous one, so it
+    /// grows with the pressure on the TrackPoint.
+    #[derive(PartialEq, Clone, Debug)]
+    pub enum AccelCurve {
+        Linear {
+            scale: f32,
+        },
+        /// The gain is `scale * (speed / reference_speed) ^ (exponent - 1)`.
+        /// Slower than `reference_speed` scrolls less, and faster scrolls more.
+        Power {
+            scale: f32,
+            exponent: f32,
+            reference_speed: f32,
+        },
+        /// Interpolates `(input, output)` points, starting from `(0, 0)`.
+        /// Beyond the last point, it keeps the gain of the last point.
+        Table(Vec<(f32, f32)>),
+    }
+
+    impl AccelCurve {
+        fn output_speed(&self, speed: f32) -> f32 {
+            match self {
+                AccelCurve::Linear { scale } => scale * speed,
+                AccelCurve::Power {
+                    scale,
+                    exponent,
+                    reference_speed,
+                } => scale * speed * (speed / reference_speed).powf(exponent - 1.0),
+                AccelCurve::Table(points) => {
+                    let mut prev = (0.0, 0.0);
+                    for &(input, output) in points {
+                        if speed <= input {
+                            if input == prev.0 {
+                                return output;
+                            }
+                            return prev.1
+                                + (output - prev.1) * (speed - prev.0) / (input - prev.0);
+                        }
+                        prev = (input, output);
+                    }
+                    if prev.0 > 0.0 {
+                        speed * prev.1 / prev.0
+                    } else {
+                        prev.1
+                    }
+                }
+            }
+        }
+
+        fn validate(&self) -> Result<()> {
+            let positive = |name: &str, value: f32| {
+                ensure!(
+                    value.is_finite() && value > 0.0,
+                    "`{}` should be positive",
+                    name
+                );
+                Ok(())
+            };
+            match self {
+                AccelCurve::Linear { scale } => positive("scale", *scale)?,
+                AccelCurve::Power {
+                    scale,
+                    exponent,
+                    reference_speed,
+                } => {
+                    positive("scale", *scale)?;
+                    positive("exponent", *exponent)?;
+                    positive("reference_speed", *reference_speed)?;
+                }
+                AccelCurve::Table(points) => {
+                    ensure!(!points.is_empty(), "`table` requires at least one point");
+                    let mut prev_input = None;
+                    for &(input, output) in points {
+                        ensure!(
+                            input.is_finite()
+                                && output.is_finite()
+                                && input >= 0.0
+                                && output >= 0.0,
+                            "`table` should not have negative speeds"
+                        );
+                        if let Some(prev_input) = prev_input {
+                            ensure!(
+                                prev_input < input,
+                                "the input speeds of `table` should increase"
+                            );
+                        }
+                        prev_input = Some(input);
+                    }
+                }
+            }
+            Ok(())
+        }
+    }
+
+    /// Tuning of the accelerated scrolling.
+    #[derive(PartialEq, Clone, Debug)]
+    pub struct AccelParams {
+        pub curve: AccelCurve,
+        /// Shorter intervals between scrolls count as this one.
+        pub min_feed_interval_secs: f32,
+        /// Longer intervals between scrolls, and the first scroll, count as this one.
+        pub max_feed_interval_secs: f32,
+    }
+
+    impl AccelParams {
+        pub(crate) fn from_section(section: &Section) -> Result<Self> {
+            section.check_keys(&[
+                "curve",
+                "scale",
+                "exponent",
+                "reference_speed",
+                "table",
+                "min_feed_interval_secs",
+                "max_feed_interval_secs",
+            ])?;
+            let parse_f32 = |value: &str| Ok(value.parse::<f32>()?);
+            let parse_point = |value: &str| {
+                let mut parts = value.splitn(2, ':');
+                let input = parse_f32(parts.next().unwrap().trim())?;
+                let output = parts
+                    .next()
+                    .ok_or_else(|| anyhow!("expected `input:output`"))?
+                    .trim();
+                Ok((input, parse_f32(output)?))
+            };
+
+            let scale = section.get("scale", parse_f32)?.unwrap_or(1.0);
+            let curve = section
+                .get("curve", |value| Ok(value.to_owned()))?
+                .unwrap_or_else(|| "power".to_owned());
+            let curve = match curve.as_str() {
+                "linear" => AccelCurve::Linear { scale },
+                "power" => AccelCurve::Power {
+                    scale,
+                    exponent: section
+                        .get("exponent", parse_f32)?
+                        .unwrap_or(DEFAULT_EXPONENT),
+                    reference_speed: section
+                        .get("reference_speed", parse_f32)?
+                        .unwrap_or(DEFAULT_REFERENCE_SPEED),
+                },
+                "table" => AccelCurve::Table(
+                    section.require("table", |value| parse_list(value, parse_point))?,
+                ),
+                _ => bail!(
+                    "line {}: `{}` is not `linear`, `power` or `table`",
+                    section.line,
+                    curve
+                ),
+            };
+            let default = Self::default();
+            let params = Self {
+                curve,
+                min_feed_interval_secs: section
+                    .get("min_feed_interval_secs", parse_f32)?
+                    .unwrap_or(default.min_feed_interval_secs),
+                max_feed_interval_secs: section
+                    .get("max_feed_interval_secs", parse_f32)?
+                    .unwrap_or(default.max_feed_interval_secs),
+            };
+            params
+                .validate()
+                .with_context(|| format!("line {}: invalid `[{}]`", section.line, section.name))?;
+            Ok(params)
+        }
+
+        pub fn validate(&self) -> Result<()> {
+            self.curve.validate()?;
+            ensure!(
+                self.min_feed_interval_secs.is_finite() && self.min_feed_interval_secs > 0.0,
+                "`min_feed_interval_secs` should be positive"
+            );
+            ensure!(
+                self.max_feed_interval_secs.is_finite()
+                    && self.min_feed_interval_secs < self.max_feed_interval_secs,
+                "`min_feed_interval_secs` should be less than `max_feed_interval_secs`"
+            );
+            Ok(())
+        }
+    }
+
+    impl Default for AccelParams {
+        fn default() -> Self {
+            // Same as the smooth scrolling.
+            let smooth = SmoothParams::default();
+            Self {
+                curve: AccelCurve::Power {
+                    scale: 1.0,
+                    exponent: DEFAULT_EXPONENT,
+                    reference_speed: DEFAULT_REFERENCE_SPEED,
+                },
+                min_feed_interval_secs: smooth.min_feed_interval_secs,
+                max_feed_interval_secs: smooth.max_feed_interval_secs,
+            }
+        }
+    }
+
+    struct PrevScroll {
+        direction: i8,
+        time: Instant,
+    }
+
+    /// The scrolling along an axis.
+    #[derive(Default)]
+    struct AxisState {
+        prev: Option<PrevScroll>,
+        error: f32,
+    }
+
+    /// Scrolls right away by the distance that the curve maps the scroll speed to.
+    /// Each axis has its own speed.
+    pub struct Accelerator<C> {
+        params: AccelParams,
+        clock: C,
+        /// Vertical and horizontal.
+        axes: [AxisState; 2],
+    }
+
+    impl<C: Clock> Accelerator<C> {
+        pub fn new(params: AccelParams, clock: C) -> Self {
+            Self {
+                params,
+                clock,
+                axes: Default::default(),
+            }
+        }
+
+        pub fn scroll(&mut self, delta: ScrollDelta) -> Vec<WheelTick> {
+            let now = self.clock.now();
+            delta
+                .axes()
+                .map(|(axis, delta)| self.scroll_axis(now, axis, delta))
+                .collect()
+        }
+
+        fn scroll_axis(&mut self, now: Instant, axis: Axis, delta: i8) -> WheelTick {
+            let state = &mut self.axes[axis_index(axis)];
+            let direction = delta.signum();
+            let min_interval = self.params.min_feed_interval_secs;
+            let max_interval = self.params.max_feed_interval_secs;
+            let interval = match state.prev {
+                Some(ref prev) if prev.direction == direction => {
+                    let elapsed = (now - prev.time).as_secs_f32();
+                    elapsed.clamp(min_interval, max_interval)
+                }
+                _ => {
+                    state.error = 0.0;
+                    max_interval
+                }
+            };
+            state.prev = Some(PrevScroll {
+                direction,
+                time: now,
+            });
+
+            let speed = delta.abs() as f32 / interval;
+            let distance = self.params.curve.output_speed(speed) * interval;
+            let delta_f32 = direction as f32 * distance * WHEEL_DELTA as f32;
+            let mut mouse_data = delta_f32 as i32;
+            // accumulate f32 -> i32 rounding errors.
+            state.error += delta_f32 - mouse_data as f32;
+            mouse_data += state.error.div_euclid(1.0) as i32;
+            state.error = state.error.rem_euclid(1.0);
+
+            WheelTick { axis, mouse_data }
+        }
+
+        /// Starts a new gesture.
+        pub fn stop(&mut self) {
+            for state in &mut self.axes {
+                state.prev = None;
+            }
+        }
+    }
+
+    impl<C: Clock> StepControl for Accelerator<C> {
+        fn scroll(&mut self, delta: ScrollDelta) -> Vec<WheelTick> {
+            Accelerator::scroll(self, delta)
+        }
+
+        fn press(&mut self) {}
+
+        fn stop(&mut self) {
+            Accelerator::stop(self);
+        }
+
+        fn tick(&mut self) -> Vec<WheelTick> {
+            Vec::new()
+        }
+    }
+
+    pub struct AccelController {
+        output: Arc<dyn OutputSink>,
+        accelerator: RefCell<Accelerator<SystemClock>>,
+    }
+
+    impl AccelController {
+        pub fn new(params: AccelParams, output: Arc<dyn OutputSink>) -> Self {
+            Self {
+                output,
+                accelerator: RefCell::new(Accelerator::new(params, SystemClock)),
+            }
+        }
+    }
+
+    impl ScrollControl for AccelController {
+        fn scroll(&self, delta: ScrollDelta) {
+            let wheels = self.accelerator.borrow_mut().scroll(delta);
+            for wheel in wheels {
+                self.output.send_wheel(wheel.axis, wheel.mouse_data)
+            }
+        }
+
+        fn press(&self) {}
+
+        fn stop(&self) {
+            self.accelerator.borrow_mut().stop();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Runs `smoother` at its tick interval, feeding each scroll before the tick of its index,
+    /// until it runs out of ticks after the last scroll. Returns the wheel events of each tick.
+    fn run_smoother(params: SmoothParams, script: &[(usize, ScrollDelta)]) -> Vec<Vec<WheelTick>> {
+        let clock = ManualClock::new();
+        let mut smoother = Smoother::new(params, clock.clone());
+        let interval = Duration::from_secs(1) / params.wheel_tick_freq;
+        let last = script.last().map_or(0, |&(index, _)| index);
+        let mut ticks = Vec::new();
+        for index in 0.. {
+            for &(_, delta) in script.iter().filter(|&&(at, _)| at == index) {
+                smoother.scroll(delta);
+            }
+            let wheels = smoother.tick();
+            if wheels.is_empty() && index >= last {
+                break;
+            }
+            ticks.push(wheels);
+            clock.advance(interval);
+        }
+        ticks
+    }
+
+    fn mouse_data(ticks: &[Vec<WheelTick>], axis: Axis) -> Vec<i32> {
+        ticks
+            .iter()
+            .flatten()
+            .filter(|wheel| wheel.axis == axis)
+            .map(|wheel| wheel.mouse_data)
+            .collect()
+    }
+
+    fn vertical(dy: i8) -> ScrollDelta {
+        ScrollDelta { dx: 0, dy }
+    }
+
+    fn horizontal(dx: i8) -> ScrollDelta {
+        ScrollDelta { dx, dy: 0 }
+    }
+
+    #[test]
+    fn smooth_single_notch() {
+        let ticks = run_smoother(SmoothParams::default(), &[(0, vertical(1))]);
+        assert_eq!(
+            mouse_data(&ticks, Axis::Vertical),
+            [
+                0, 1, 1, 0, 1, 1, 0, 1, 1, 0, 1, 1, 0, 1, 0, 1, 1, 0, 1, 0, 1, 0, 1, 0, 1, 0, 1, 0,
+                1, 0, 1, 0, 0, 1, 0, 1, 0, 0, 1, 0, 0, 1, 0, 0, 1, 0, 0, 0, 1, 0, 0, 0, 1, 0, 0, 0,
+                0, 0, 1, 0, 0, 0, 0, 0, 0, 0,
+            ]
+        );
+        assert!(mouse_data(&ticks, Axis::Horizontal).is_empty());
+    }
+
+    #[test]
+    fn smooth_feeds_and_reversal() {
+        let script = [
+            (0, vertical(1)),
+            (6, vertical(1)),
+            (12, vertical(2)),
+            (18, vertical(-1)),
+        ];
+        let ticks = run_smoother(SmoothParams::default(), &script);
+        assert_eq!(
+            mouse_data(&ticks, Axis::Vertical),
+            [
+                0, 1, 1, 0, 1, 1, 7, 9, 11, 10, 10, 8, 9, 11, 13, 13, 15, 16, -1, -1, -1, 0, -1,
+                -1, 0, -1, -1, 0, -1, -1, 0, -1, 0, -1, -1, 0, -1, 0, -1, 0, -1, 0, -1, 0, -1, 0,
+                -1, 0, -1, 0, 0, -1, 0, -1, 0, 0, -1, 0, 0, -1, 0, 0, -1, 0, 0, 0, -1, 0, 0, 0, -1,
+                0, 0, 0, 0, 0, -1, 0, 0, 0, 0, 0, 0, 0,
+            ]
+        );
+    }
+
+    #[test]
+    fn smooth_axes_are_independent() {
+        let diagonal = run_smoother(
+            SmoothParams::default(),
+            &[
+                (0, vertical(1)),
+                (6, horizontal(1)),
+                (12, ScrollDelta { dx: 1, dy: 1 }),
+            ],
+        );
+        let vertical_only = run_smoother(
+            SmoothParams::default(),
+            &[(0, vertical(1)), (12, vertical(1))],
+        );
+        let horizontal_only = run_smoother(
+            SmoothParams::default(),
+            &[(0, horizontal(1)), (6, horizontal(1))],
+        );
+        assert_eq!(
+            mouse_data(&diagonal, Axis::Vertical),
+            mouse_data(&vertical_only, Axis::Vertical)
+        );
+        assert_eq!(
+            mouse_data(&diagonal, Axis::Horizontal),
+            mouse_data(&horizontal_only, Axis::Horizontal)
+        );
+        assert_eq!(
+            mouse_data(&diagonal, Axis::Horizontal),
+            [0, 1, 1, 0, 1, 1, 7, 9, 11, 10, 10, 8, 8, 6, 6, 5, 5, 3, 0]
+        );
+    }
+
+    #[test]
+    fn smooth_stop_ends_both_axes() {
+        let mut smoother = Smoother::new(SmoothParams::default(), ManualClock::new());
+        assert!(smoother.scroll(ScrollDelta { dx: 1, dy: 1 }));
+        assert_eq!(smoother.tick().len(), 2);
+        smoother.stop();
+        assert!(smoother.tick().is_empty());
+    }
+
+    /// Runs `kinetic` until it stops coasting, feeding each scroll before the tick of its index.
+    fn run_kinetic(params: KineticParams, script: &[(usize, ScrollDelta)]) -> Vec<Vec<WheelTick>> {
+        let mut kinetic = Kinetic::new(params);
+        let last = script.last().map_or(0, |&(index, _)| index);
+        let mut ticks = Vec::new();
+        for index in 0.. {
+            for &(_, delta) in script.iter().filter(|&&(at, _)| at == index) {
+                kinetic.scroll(delta);
+            }
+            let wheels = kinetic.tick();
+            if wheels.is_empty() && index >= last {
+                break;
+            }
+            ticks.push(wheels);
+        }
+        ticks
+    }
+
+    #[test]
+    fn kinetic_single_notch() {
+        let ticks = run_kinetic(KineticParams::default(), &[(0, vertical(1))]);
+        assert_eq!(
+            mouse_data(&ticks, Axis::Vertical),
+            [
+                4, 3, 4, 4, 3, 4, 3, 3, 3, 3, 3, 3, 2, 3, 3, 2, 2, 3, 2, 2, 2, 2, 2, 2, 1, 2, 2, 2,
+                1, 2, 1, 2, 1, 1, 2, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 0, 1, 1, 1, 0, 1, 1,
+                0, 1, 0, 1, 1, 0, 1,
+            ]
+        );
+    }
+
+    #[test]
+    fn kinetic_push_is_independent_of_friction() {
+        let params = KineticParams {
+            friction: 0.1,
+            ..KineticParams::default()
+        };
+        let ticks = run_kinetic(params, &[(0, vertical(1))]);
+        assert!(ticks[0][0].mouse_data > 0);
+        assert!(mouse_data(&ticks, Axis::Vertical).iter().sum::<i32>() > WHEEL_DELTA);
+    }
+
+    #[test]
+    fn kinetic_adds_velocity_on_another_axis() {
+        let params = KineticParams::default();
+        let ticks = run_kinetic(params, &[(0, vertical(1)), (10, horizontal(1))]);
+        let vertical_only = run_kinetic(params, &[(0, vertical(1))]);
+        let horizontal_only = run_kinetic(params, &[(0, horizontal(1))]);
+        assert_eq!(
+            mouse_data(&ticks, Axis::Vertical),
+            mouse_data(&vertical_only, Axis::Vertical)
+        );
+        assert_eq!(
+            mouse_data(&ticks, Axis::Horizontal),
+            mouse_data(&horizontal_only, Axis::Horizontal)
+        );
+    }
+
+    #[test]
+    fn kinetic_diagonal_keeps_the_motion() {
+        let params = KineticParams::default();
+        let ticks = run_kinetic(
+            params,
+            &[(0, vertical(1)), (10, ScrollDelta { dx: 1, dy: 1 })],
+        );
+        let vertical_only = run_kinetic(params, &[(0, vertical(1))]);
+        let horizontal_only = run_kinetic(params, &[(0, horizontal(1))]);
+        let distance = |ticks: &[Vec<WheelTick>], axis| mouse_data(ticks, axis).iter().sum::<i32>();
+        assert!(distance(&ticks, Axis::Vertical) > distance(&vertical_only, Axis::Vertical));
+        assert_eq!(
+            mouse_data(&ticks, Axis::Horizontal),
+            mouse_data(&horizontal_only, Axis::Horizontal)
+        );
+    }
+
+    #[test]
+    fn kinetic_halt_stops_coasting() {
+        let mut kinetic = Kinetic::new(KineticParams::default());
+        kinetic.scroll(vertical(1));
+        StepControl::stop(&mut kinetic);
+        assert!(!kinetic.tick().is_empty());
+        StepControl::halt(&mut kinetic);
+        assert!(kinetic.tick().is_empty());
+    }
+
+    #[test]
+    fn accel_feed_interval_bounds() {
+        let scroll_twice = |params: AccelParams, interval: Duration| {
+            let clock = ManualClock::new();
+            let mut accelerator = Accelerator::new(params, clock.clone());
+            let first = accelerator.scroll(vertical(1));
+            clock.advance(interval);
+            (first, accelerator.scroll(vertical(1)))
+        };
+
+        let default = AccelParams::default();
+        // A scroll as fast as the min interval, and one faster than it, are the same speed.
+        let at_min = scroll_twice(default.clone(), Duration::from_millis(15));
+        let faster = scroll_twice(default.clone(), Duration::from_millis(1));
+        assert_eq!(at_min, faster);
+
+        let bounded = AccelParams {
+            min_feed_interval_secs: 0.05,
+            max_feed_interval_secs: 0.1,
+            ..default.clone()
+        };
+        let (first, _) = scroll_twice(default, Duration::from_millis(1));
+        let (bounded_first, bounded_fast) = scroll_twice(bounded.clone(), Duration::from_millis(1));
+        // The first scroll counts as the max interval.
+        assert!(bounded_first[0].mouse_data > first[0].mouse_data);
+        let (_, bounded_at_min) = scroll_twice(bounded, Duration::from_millis(50));
+        assert_eq!(bounded_fast, bounded_at_min);
+    }
 }
@@ -0,0 +1,258 @@
+//! Transforms of the scroll input, applied by [`TPMiddle`](crate::tpmiddle::TPMiddle) before the
+//! modifier rules and any controller.
+
+use anyhow::*;
+
+use crate::config::Section;
+use crate::input::{Axis, ScrollDelta};
+
+/// How an input axis scrolls.
+#[derive(PartialEq, Copy, Clone, Debug)]
+pub struct AxisTransform {
+    /// The axis to scroll.
+    pub axis: Axis,
+    pub invert: bool,
+    pub multiplier: f32,
+}
+
+impl AxisTransform {
+    fn identity(axis: Axis) -> Self {
+        Self {
+            axis,
+            invert: false,
+            multiplier: 1.0,
+        }
+    }
+
+    fn from_section(section: &Section, axis: Axis) -> Result<Self> {
+        section.check_keys(&["axis", "invert", "multiplier"])?;
+        let identity = Self::identity(axis);
+        let multiplier = section
+            .get("multiplier", |value| Ok(value.parse::<f32>()?))?
+            .unwrap_or(identity.multiplier);
+        if !(multiplier.is_finite() && multiplier > 0.0) {
+            bail!(
+                "line {}: `multiplier` should be positive. Use `invert` to invert the direction",
+                section.line
+            );
+        }
+        Ok(Self {
+            axis: section
+                .get("axis", |value| match value {
+                    "vertical" => Ok(Axis::Vertical),
+                    "horizontal" => Ok(Axis::Horizontal),
+                    _ => bail!("`{}` is not `vertical` or `horizontal`", value),
+                })?
+                .unwrap_or(identity.axis),
+            invert: section
+                .get("invert", |value| Ok(value.parse::<bool>()?))?
+                .unwrap_or(identity.invert),
+            multiplier,
+        })
+    }
+}
+
+#[derive(PartialEq, Copy, Clone, Debug)]
+pub struct AxisTransforms {
+    pub vertical: AxisTransform,
+    pub horizontal: AxisTransform,
+}
+
+impl AxisTransforms {
+    /// Reads the `[vertical]` and `[horizontal]` sections, if any.
+    pub fn from_sections(sections: &[Section]) -> Result<Self> {
+        let mut transforms = Self::default();
+        for section in sections {
+            match section.name.as_str() {
+                "vertical" => {
+                    transforms.vertical = AxisTransform::from_section(section, Axis::Vertical)?
+                }
+                "horizontal" => {
+                    transforms.horizontal = AxisTransform::from_section(section, Axis::Horizontal)?
+                }
+                _ => {}
+            }
+        }
+        Ok(transforms)
+    }
+
+    /// Natural scrolling.
+    pub fn invert(&mut self) {
+        self.vertical.invert = !self.vertical.invert;
+        self.horizontal.invert = !self.horizontal.invert;
+    }
+
+    pub fn swap(&mut self) {
+        std::mem::swap(&mut self.vertical.axis, &mut self.horizontal.axis);
+    }
+}
+
+impl Default for AxisTransforms {
+    fn default() -> Self {
+        Self {
+            vertical: AxisTransform::identity(Axis::Vertical),
+            horizontal: AxisTransform::identity(Axis::Horizontal),
+        }
+    }
+}
+
+pub(crate) struct Transformer {
+    transforms: AxisTransforms,
+    /// Fractions of the input axes that are not scrolled yet.
+    remainders: [f32; 2],
+}
+
+impl Transformer {
+    pub(crate) fn new(transforms: AxisTransforms) -> Self {
+        Self {
+            transforms,
+            remainders: [0.0; 2],
+        }
+    }
+
+    pub(crate) fn apply(&mut self, delta: ScrollDelta) -> ScrollDelta {
+        let mut output = ScrollDelta::default();
+        for (axis, delta) in delta.axes() {
+            if let Some((axis, delta)) = self.apply_axis(axis, delta) {
+                let output = match axis {
+                    Axis::Vertical => &mut output.dy,
+                    Axis::Horizontal => &mut output.dx,
+                };
+                *output = output.saturating_add(delta);
+            }
+        }
+        output
+    }
+
+    fn apply_axis(&mut self, axis: Axis, delta: i8) -> Option<(Axis, i8)> {
+        let (transform, remainder) = match axis {
+            Axis::Vertical => (&self.transforms.vertical, &mut self.remainders[0]),
+            Axis::Horizontal => (&self.transforms.horizontal, &mut self.remainders[1]),
+        };
+        let mut value = delta as f32 * transform.multiplier;
+        if transform.invert {
+            value = -value;
+        }
+        if *remainder * value < 0.0 {
+            // Direction is changed. Discard the fraction.
+            *remainder = 0.0;
+        }
+        *remainder += value;
+        let units = remainder.trunc();
+        *remainder -= units;
+        if units == 0.0 {
+            return None;
+        }
+        // Saturates at the bounds of `i8`.
+        Some((transform.axis, units as i8))
+    }
+
+    pub(crate) fn reset(&mut self) {
+        self.remainders = [0.0; 2];
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::config;
+
+    fn transformer(config: &str) -> Transformer {
+        let sections = config::parse(config).unwrap();
+        Transformer::new(AxisTransforms::from_sections(&sections).unwrap())
+    }
+
+    fn apply_all(transformer: &mut Transformer, dx: i8, dys: &[i8]) -> Vec<(i8, i8)> {
+        dys.iter()
+            .map(|&dy| {
+                let output = transformer.apply(ScrollDelta { dx, dy });
+                (output.dx, output.dy)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn identity() {
+        let mut transformer = transformer("");
+        assert_eq!(
+            apply_all(&mut transformer, -2, &[1, 0, 127]),
+            [(-2, 1), (-2, 0), (-2, 127)]
+        );
+    }
+
+    #[test]
+    fn multiplier() {
+        let mut transformer =
+            transformer("[vertical]\nmultiplier = 3\n[horizontal]\nmultiplier = 2");
+        assert_eq!(apply_all(&mut transformer, 1, &[1, -2]), [(2, 3), (2, -6)]);
+        // Saturates at the bounds of `i8`.
+        assert_eq!(apply_all(&mut transformer, -100, &[100]), [(-128, 127)]);
+    }
+
+    #[test]
+    fn inversion() {
+        let mut transformer = transformer("[vertical]\ninvert = true");
+        assert_eq!(apply_all(&mut transformer, 1, &[1, -3]), [(1, -1), (1, 3)]);
+
+        let mut transforms = AxisTransforms::default();
+        transforms.invert();
+        let mut transformer = Transformer::new(transforms);
+        assert_eq!(apply_all(&mut transformer, 1, &[-1]), [(-1, 1)]);
+    }
+
+    #[test]
+    fn swap() {
+        let mut transforms = AxisTransforms::from_sections(
+            &config::parse("[vertical]\nmultiplier = 2\n[horizontal]\ninvert = true").unwrap(),
+        )
+        .unwrap();
+        transforms.swap();
+        let mut transformer = Transformer::new(transforms);
+        // The vertical input doubles along the horizontal axis, and vice versa.
+        assert_eq!(apply_all(&mut transformer, 1, &[3]), [(6, -1)]);
+        // Both along the same axis add up.
+        let mut transforms = AxisTransforms::default();
+        transforms.horizontal.axis = Axis::Vertical;
+        let mut transformer = Transformer::new(transforms);
+        assert_eq!(apply_all(&mut transformer, 2, &[3]), [(0, 5)]);
+    }
+
+    #[test]
+    fn rounding_carry() {
+        let mut transformer = transformer("[vertical]\nmultiplier = 0.4");
+        // 0.4, 0.8, 1.2, 0.6, 1.0
+        assert_eq!(
+            apply_all(&mut transformer, 0, &[1, 1, 1, 1, 1]),
+            [(0, 0), (0, 0), (0, 1), (0, 0), (0, 1)]
+        );
+        // A direction change discards the fraction.
+        assert_eq!(
+            apply_all(&mut transformer, 0, &[1, -1, -1, -1]),
+            [(0, 0), (0, 0), (0, 0), (0, -1)]
+        );
+        // So does a reset.
+        apply_all(&mut transformer, 0, &[1, 1]);
+        transformer.reset();
+        assert_eq!(apply_all(&mut transformer, 0, &[1, 1]), [(0, 0), (0, 0)]);
+    }
+
+    #[test]
+    fn rejects_invalid_sections() {
+        for config in &[
+            "[vertical]\nmultiplier = 0",
+            "[vertical]\nmultiplier = -1",
+            "[vertical]\nmultiplier = NaN",
+            "[horizontal]\naxis = diagonal",
+            "[horizontal]\ninvert = yes",
+            "[horizontal]\nscale = 2",
+        ] {
+            let sections = config::parse(config).unwrap();
+            assert!(
+                AxisTransforms::from_sections(&sections).is_err(),
+                "{}",
+                config
+            );
+        }
+    }
+}
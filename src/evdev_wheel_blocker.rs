@@ -0,0 +1,223 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::Read;
+use std::os::unix::fs::OpenOptionsExt;
+use std::os::unix::io::AsRawFd;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use anyhow::*;
+use log::*;
+
+use crate::input::uinput::VirtualPointer;
+use crate::input::Event;
+use crate::util::{has_bit, is_event_node, SYSFS_INPUT};
+
+const EVIOCGRAB: libc::c_ulong = 0x4004_4590;
+
+const EV_SYN: u16 = 0x00;
+const EV_REL: u16 = 0x02;
+const SYN_REPORT: u16 = 0x00;
+
+const REL_X: u16 = 0x00;
+const REL_Y: u16 = 0x01;
+const REL_HWHEEL: u16 = 0x06;
+const REL_WHEEL: u16 = 0x08;
+const REL_WHEEL_HI_RES: u16 = 0x0b;
+const REL_HWHEEL_HI_RES: u16 = 0x0c;
+
+const KEY_A: usize = 30;
+
+/// Grabs the native mouse interface of the keyboard exclusively, and re-emits everything but wheel
+/// events through the virtual pointer.
+/// Counterpart of `WheelBlocker` of Windows that blocks native wheel events with a mouse hook.
+///
+/// It also captures the pointer motion for the sticky scroll instead of re-emitting it.
+pub struct WheelBlocker {
+    vendor_id: u16,
+    product_id: u16,
+    pointer: Arc<VirtualPointer>,
+    devices: Vec<File>,
+    buffer: Vec<libc::input_event>,
+    pending: Vec<libc::input_event>,
+    capture_motion: bool,
+    /// The captured motion of the current report, right and down.
+    motion: (i32, i32),
+}
+
+impl WheelBlocker {
+    /// The mouse node may arrive later than the hidraw nodes. It blocks nothing until then, and
+    /// [`rescan_target_devices`] grabs the node when it arrives.
+    ///
+    /// [`rescan_target_devices`]: Self::rescan_target_devices
+    pub fn new(vendor_id: u16, product_id: u16, pointer: Arc<VirtualPointer>) -> Result<Self> {
+        const BUFFER_SIZE: usize = 64;
+        let mut wheel_blocker = Self {
+            vendor_id,
+            product_id,
+            pointer,
+            devices: Vec::new(),
+            buffer: vec![empty_event(); BUFFER_SIZE],
+            pending: Vec::new(),
+            capture_motion: false,
+            motion: (0, 0),
+        };
+        wheel_blocker.rescan_target_devices()?;
+        Ok(wheel_blocker)
+    }
+
+    pub fn rescan_target_devices(&mut self) -> Result<()> {
+        // Release the previous grabs first.
+        self.devices.clear();
+        for devnode in get_target_devnodes(self.vendor_id, self.product_id)? {
+            let file = OpenOptions::new()
+                .read(true)
+                .custom_flags(libc::O_NONBLOCK)
+                .open(&devnode)
+                .with_context(|| format!("opening {:?}", devnode))?;
+            let result = unsafe { libc::ioctl(file.as_raw_fd(), EVIOCGRAB as _, 1) };
+            if result < 0 {
+                return Err(std::io::Error::last_os_error())
+                    .with_context(|| format!("grabbing {:?}", devnode));
+            }
+            trace!("WheelBlocker blocks: {:?}", devnode);
+            self.devices.push(file);
+        }
+
+        if self.devices.is_empty() {
+            debug!("WheelBlocker waits for the mouse node");
+        }
+        Ok(())
+    }
+
+    /// Whether it grabs any node now.
+    pub fn is_blocking(&self) -> bool {
+        !self.devices.is_empty()
+    }
+
+    pub fn set_capture_motion(&mut self, capture_motion: bool) {
+        self.capture_motion = capture_motion;
+    }
+
+    pub fn pollfds(&self) -> impl Iterator<Item = libc::pollfd> + '_ {
+        self.devices.iter().map(|file| libc::pollfd {
+            fd: file.as_raw_fd(),
+            events: libc::POLLIN,
+            revents: 0,
+        })
+    }
+
+    /// Re-emits the events of the devices that are ready in `fds`, the polled result of
+    /// [`pollfds`]. Returns the captured motion.
+    ///
+    /// [`pollfds`]: Self::pollfds
+    pub fn process_polled(&mut self, fds: &[libc::pollfd]) -> Result<Vec<Event>> {
+        let mut captured = Vec::new();
+        let mut disconnected = Vec::new();
+        for (index, (fd, file)) in fds.iter().zip(self.devices.iter_mut()).enumerate() {
+            if fd.revents & (libc::POLLERR | libc::POLLHUP | libc::POLLNVAL) != 0 {
+                disconnected.push(index);
+                continue;
+            }
+            if fd.revents & libc::POLLIN == 0 {
+                continue;
+            }
+
+            let events = match read_events(file, &mut self.buffer) {
+                Ok(events) => events,
+                Err(err) if err.kind() == std::io::ErrorKind::WouldBlock => continue,
+                Err(err) => return Err(err.into()),
+            };
+            for event in events {
+                match (event.type_, event.code) {
+                    (EV_REL, REL_WHEEL)
+                    | (EV_REL, REL_HWHEEL)
+                    | (EV_REL, REL_WHEEL_HI_RES)
+                    | (EV_REL, REL_HWHEEL_HI_RES) => {}
+                    (EV_REL, REL_X) if self.capture_motion => self.motion.0 += event.value,
+                    (EV_REL, REL_Y) if self.capture_motion => self.motion.1 += event.value,
+                    (EV_SYN, SYN_REPORT) => {
+                        let (dx, dy) = std::mem::take(&mut self.motion);
+                        if (dx, dy) != (0, 0) {
+                            captured.push(Event::Motion { dx, dy });
+                        }
+                        if !self.pending.is_empty() {
+                            self.pending.push(*event);
+                            self.pointer.emit(&self.pending);
+                            self.pending.clear();
+                        }
+                    }
+                    _ => self.pending.push(*event),
+                }
+            }
+        }
+
+        for index in disconnected.into_iter().rev() {
+            // It will be grabbed again when it comes back.
+            debug!("The evdev node is disconnected");
+            self.devices.remove(index);
+        }
+
+        Ok(captured)
+    }
+}
+
+fn read_events<'a>(
+    file: &mut File,
+    buffer: &'a mut [libc::input_event],
+) -> std::io::Result<&'a [libc::input_event]> {
+    const EVENT_SIZE: usize = std::mem::size_of::<libc::input_event>();
+    let bytes = unsafe {
+        std::slice::from_raw_parts_mut(buffer.as_mut_ptr() as *mut u8, buffer.len() * EVENT_SIZE)
+    };
+    let size = file.read(bytes)?;
+    Ok(&buffer[..size / EVENT_SIZE])
+}
+
+fn empty_event() -> libc::input_event {
+    libc::input_event {
+        time: libc::timeval {
+            tv_sec: 0,
+            tv_usec: 0,
+        },
+        type_: 0,
+        code: 0,
+        value: 0,
+    }
+}
+
+/// Finds event nodes of the device that emit wheel events, but aren't keyboards.
+fn get_target_devnodes(vendor_id: u16, product_id: u16) -> Result<Vec<PathBuf>> {
+    let mut devnodes = Vec::new();
+    for entry in fs::read_dir(SYSFS_INPUT)? {
+        let syspath = entry?.path();
+        match is_target(&syspath, vendor_id, product_id) {
+            Ok(true) => devnodes.push(Path::new("/dev/input").join(syspath.file_name().unwrap())),
+            Ok(false) => {}
+            Err(err) => {
+                // The device is spuriously disconnected while enumerating
+                debug!("Error while reading {:?}: {}", syspath, err);
+            }
+        }
+    }
+    Ok(devnodes)
+}
+
+pub fn is_target(syspath: &Path, vendor_id: u16, product_id: u16) -> Result<bool> {
+    if !is_event_node(syspath) {
+        return Ok(false);
+    }
+
+    let read = |name: &str| -> Result<String> {
+        Ok(fs::read_to_string(syspath.join("device").join(name))?
+            .trim()
+            .to_owned())
+    };
+    if u16::from_str_radix(&read("id/vendor")?, 16)? != vendor_id
+        || u16::from_str_radix(&read("id/product")?, 16)? != product_id
+    {
+        return Ok(false);
+    }
+
+    Ok(has_bit(&read("capabilities/rel")?, REL_WHEEL as usize)
+        && !has_bit(&read("capabilities/key")?, KEY_A))
+}
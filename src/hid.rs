@@ -1,11 +1,16 @@
 use std::fmt;
 use std::str::FromStr;
+use std::sync::{Mutex, MutexGuard, PoisonError, TryLockError};
+use std::time::{Duration, Instant};
 
 use anyhow::*;
-use hidapi::{HidApi, HidDevice, HidResult};
+use hidapi::{HidApi, HidDevice};
 use thiserror::*;
 use log::*;
 
+use crate::protocol::{Command, CommandSet, ReportType};
+use crate::registry::DeviceModel;
+
 pub const VID_LENOVO: u16 = 0x17EF;
 pub const PID_USB: u16 = 0x60EE;
 pub const PID_BT: u16 = 0x60E1;
@@ -18,18 +23,6 @@ pub struct DeviceInfo {
     pub usage: u16,
 }
 
-impl DeviceInfo {
-    pub fn transport(&self) -> Option<Transport> {
-        if self.product_id == PID_USB {
-            Some(Transport::USB)
-        } else if self.product_id == PID_BT {
-            Some(Transport::BT)
-        } else {
-            None
-        }
-    }
-}
-
 impl From<&hidapi::DeviceInfo> for DeviceInfo {
     fn from(di: &hidapi::DeviceInfo) -> Self {
         Self {
@@ -59,94 +52,12 @@ impl std::fmt::Debug for DeviceInfo {
     }
 }
 
-const DEVICE_INFO_SET_FEATURES_USB: DeviceInfo = DeviceInfo {
-    vendor_id: VID_LENOVO,
-    product_id: PID_USB,
-    usage_page: 0x0C,
-    usage: 0x01,
-};
-
-const DEVICE_INFO_SET_FEATURES_BT: DeviceInfo = DeviceInfo {
-    vendor_id: VID_LENOVO,
-    product_id: PID_BT,
-    usage_page: 0xFF01,
-    usage: 0x01,
-};
-
-const DEVICE_INFO_MIDDLE_BUTTON_HID_USB: DeviceInfo = DeviceInfo {
-    vendor_id: VID_LENOVO,
-    product_id: PID_USB,
-    usage_page: 0xFFA0,
-    usage: 0x01,
-};
-
-const DEVICE_INFO_MIDDLE_BUTTON_HID_BT: DeviceInfo = DeviceInfo {
-    vendor_id: VID_LENOVO,
-    product_id: PID_BT,
-    usage_page: 0xFF00,
-    usage: 0x01,
-};
-
-const DEVICE_INFO_NON_NATIVE_WHEEL_USB: DeviceInfo = DeviceInfo {
-    vendor_id: VID_LENOVO,
-    product_id: PID_USB,
-    usage_page: 0xFF10,
-    usage: 0x01,
-};
-
-const DEVICE_INFO_NON_NATIVE_WHEEL_BT: DeviceInfo = DeviceInfo {
-    vendor_id: VID_LENOVO,
-    product_id: PID_BT,
-    usage_page: 0xFF10,
-    usage: 0x01,
-};
-
-pub const DEVICE_INFO_WHEEL_HID_BT: DeviceInfo = DeviceInfo {
-    vendor_id: VID_LENOVO,
-    product_id: PID_BT,
-    usage_page: 0x01,
-    usage: 0x02,
-};
-
-pub const DEVICE_INFOS_NOTIFY: &[DeviceInfo] = &[
-    DEVICE_INFO_MIDDLE_BUTTON_HID_USB,
-    DEVICE_INFO_MIDDLE_BUTTON_HID_BT,
-    DEVICE_INFO_WHEEL_HID_BT,
-];
-
-pub const DEVICE_INFOS_SINK: &[DeviceInfo] = &[
-    DEVICE_INFO_MIDDLE_BUTTON_HID_USB,
-    DEVICE_INFO_NON_NATIVE_WHEEL_USB,
-    DEVICE_INFO_MIDDLE_BUTTON_HID_BT,
-    DEVICE_INFO_NON_NATIVE_WHEEL_BT,
-    // sink to block
-    DEVICE_INFO_WHEEL_HID_BT,
-];
-
-const DEVICE_INFO_USB: &[DeviceInfo] = &[
-    DEVICE_INFO_MIDDLE_BUTTON_HID_USB,
-    DEVICE_INFO_NON_NATIVE_WHEEL_USB,
-];
-const DEVICE_INFO_BT: &[DeviceInfo] = &[
-    DEVICE_INFO_MIDDLE_BUTTON_HID_BT,
-    DEVICE_INFO_NON_NATIVE_WHEEL_BT,
-];
-
 #[derive(Eq, PartialEq, Copy, Clone, Debug)]
 pub enum Transport {
     USB,
     BT,
 }
 
-impl Transport {
-    pub fn device_info(&self) -> &'static [DeviceInfo] {
-        match self {
-            Self::USB => DEVICE_INFO_USB,
-            Self::BT => DEVICE_INFO_BT,
-        }
-    }
-}
-
 impl FromStr for Transport {
     type Err = anyhow::Error;
 
@@ -172,99 +83,132 @@ impl fmt::Display for Transport {
 pub enum InitializeError {
     #[error("Hid error: {0}")]
     HidError(#[from] hidapi::HidError),
-    #[error("Cannot find a keyboard over {0}")]
-    CannotFindKeyboard(Transport),
+    #[error("Cannot find {name} over {transport}")]
+    CannotFindKeyboard { name: String, transport: Transport },
+    #[error("Hid API is kept busy while panicking")]
+    HidApiBusy,
+}
+
+lazy_static! {
+    /// hidapi refuses to create a second `HidApi` while one is alive, so the connections and the
+    /// restoration on exit, which may run on the signal thread, share this one.
+    static ref HID_API: Mutex<Option<HidApi>> = Mutex::new(None);
+}
+
+/// Locks the shared `HidApi`, waiting for the other thread using it.
+fn lock_hid_api() -> Result<MutexGuard<'static, Option<HidApi>>, InitializeError> {
+    if !std::thread::panicking() {
+        return Ok(HID_API.lock().unwrap_or_else(PoisonError::into_inner));
+    }
+
+    // In the panic hook, the panicking thread itself may hold the lock, so don't wait forever.
+    const PANIC_TIMEOUT: Duration = Duration::from_secs(1);
+    let deadline = Instant::now() + PANIC_TIMEOUT;
+    loop {
+        match HID_API.try_lock() {
+            Ok(guard) => return Ok(guard),
+            Err(TryLockError::Poisoned(err)) => return Ok(err.into_inner()),
+            Err(TryLockError::WouldBlock) if Instant::now() < deadline => {
+                std::thread::sleep(Duration::from_millis(10))
+            }
+            Err(TryLockError::WouldBlock) => return Err(InitializeError::HidApiBusy),
+        }
+    }
 }
 
 pub fn initialize_keyboard(
-    transport: Transport,
+    model: &DeviceModel,
     sensitivity: Option<u8>,
     fn_lock: Option<bool>,
 ) -> Result<(), InitializeError> {
-    let api = HidApi::new()?;
+    with_keyboard(model, "set keyboard feature", |device| {
+        set_keyboard_features(device, &model.command_set, sensitivity, fn_lock)
+    })
+}
+
+/// Turns the native middle button back on.
+pub fn restore_keyboard(model: &DeviceModel) -> Result<(), InitializeError> {
+    with_keyboard(model, "restore keyboard", |device| {
+        apply_commands(
+            device,
+            &model.command_set,
+            Some(Command::NativeMiddleButton(true)),
+        )
+    })
+}
+
+/// Runs `f` with the command collection of the keyboard.
+fn with_keyboard<T>(
+    model: &DeviceModel,
+    description: &str,
+    f: impl Fn(&HidDevice) -> Result<T>,
+) -> Result<T, InitializeError> {
+    let mut guard = lock_hid_api()?;
+    let api = match guard.take() {
+        Some(mut api) => {
+            api.refresh_devices()?;
+            api
+        }
+        None => HidApi::new()?,
+    };
+    let api = guard.insert(api);
 
+    let target = model.device_info(model.command_collection);
     for di in api.device_list() {
-        let device_info = DeviceInfo::from(di);
-        if transport == Transport::USB && device_info == DEVICE_INFO_SET_FEATURES_USB{
-            match set_keyboard_features::<USB>(&di.open_device(&api)?, sensitivity, fn_lock) {
-                Ok(()) => return Ok(()),
-                Err(err) => {
-                    info!("Failed to set keyboard feature path={path:?}, err={err:?}", path=di.path(), err = err);
-                },
-            }
-        } else if transport == Transport::BT && device_info == DEVICE_INFO_SET_FEATURES_BT
-        {
-            match set_keyboard_features::<BT>(&di.open_device(&api)?, sensitivity, fn_lock) {
-                Ok(()) => return Ok(()),
-                Err(err) => {
-                    info!("Failed to set keyboard feature path={path:?}, err={err:?}", path=di.path(), err = err);
-                },
+        if DeviceInfo::from(di) != target {
+            continue;
+        }
+        match f(&di.open_device(api)?) {
+            Ok(result) => return Ok(result),
+            Err(err) => {
+                info!(
+                    "Failed to {description} path={path:?}, err={err:?}",
+                    description = description,
+                    path = di.path(),
+                    err = err
+                );
             }
         }
     }
-    return Err(InitializeError::CannotFindKeyboard(transport));
-}
-
-trait SetFeatures {
-    fn set_sensitivity(device: &HidDevice, sensitivity: u8) -> HidResult<()>;
-    fn set_fn_lock(device: &HidDevice, enable: bool) -> HidResult<()>;
-    fn set_native_middle_button(device: &HidDevice, enable: bool) -> HidResult<()>;
+    Err(InitializeError::CannotFindKeyboard {
+        name: model.name.clone(),
+        transport: model.transport,
+    })
 }
 
-fn set_keyboard_features<T: SetFeatures>(
+fn set_keyboard_features(
     device: &HidDevice,
+    set: &CommandSet,
     sensitivity: Option<u8>,
     fn_lock: Option<bool>,
 ) -> Result<()> {
-    if let Some(sensitivity) = sensitivity {
-        T::set_sensitivity(&device, sensitivity).context("setting sensitivity")?;
-    }
-    if let Some(fn_lock) = fn_lock {
-        T::set_fn_lock(&device, fn_lock).context("setting fn lock")?;
-    }
-    T::set_native_middle_button(&device, false).map_err(|err| anyhow!("cannot set native middle button: {}", err))?;
-    Ok(())
+    let commands = sensitivity
+        .map(Command::Sensitivity)
+        .into_iter()
+        .chain(fn_lock.map(Command::FnLock))
+        .chain(Some(Command::NativeMiddleButton(false)));
+    apply_commands(device, set, commands)
 }
 
-struct USB;
-impl SetFeatures for USB {
-    fn set_sensitivity(device: &HidDevice, sensitivity: u8) -> HidResult<()> {
-        assert!(sensitivity >= 1 && sensitivity <= 9);
-        device.send_feature_report(&[0x13, 0x02, sensitivity, 0x00, 0x00, 0x00, 0x00, 0x00])
-    }
-
-    fn set_fn_lock(device: &HidDevice, enable: bool) -> HidResult<()> {
-        let code = if enable { 0x01 } else { 0x00 };
-        device.send_feature_report(&[0x13, 0x05, code, 0x00, 0x00, 0x00, 0x00, 0x00])
-    }
-
-    fn set_native_middle_button(device: &HidDevice, enable: bool) -> HidResult<()> {
-        // 0x00: Keyboard sends scroll events
-        // 0x01: "ThinkPad preferred scroll".
-        let code = if enable { 0x00 } else { 0x01 };
-        device.send_feature_report(&[0x13, 0x09, code, 0x00, 0x00, 0x00, 0x00, 0x00])
+fn apply_commands(
+    device: &HidDevice,
+    set: &CommandSet,
+    commands: impl IntoIterator<Item = Command>,
+) -> Result<()> {
+    for command in commands {
+        send_command(device, set, command)
+            .with_context(|| format!("setting {:?}", command.setting()))?;
     }
+    Ok(())
 }
 
-struct BT;
-impl SetFeatures for BT {
-    fn set_sensitivity(device: &HidDevice, sensitivity: u8) -> HidResult<()> {
-        assert!(sensitivity >= 1 && sensitivity <= 9);
-        device.write(&[0x18, 0x02, sensitivity])?;
-        Ok(())
-    }
-
-    fn set_fn_lock(device: &HidDevice, enable: bool) -> HidResult<()> {
-        let code = if enable { 0x01 } else { 0x00 };
-        device.write(&[0x18, 0x05, code])?;
-        Ok(())
-    }
-
-    fn set_native_middle_button(device: &HidDevice, enable: bool) -> HidResult<()> {
-        // 0x00: Keyboard sends scroll events
-        // 0x01: "ThinkPad preferred scroll".
-        let code = if enable { 0x00 } else { 0x01 };
-        device.write(&[0x18, 0x09, code])?;
-        Ok(())
+fn send_command(device: &HidDevice, set: &CommandSet, command: Command) -> Result<()> {
+    let report = command.encode(set)?;
+    match set.report_type {
+        ReportType::Feature => device.send_feature_report(&report)?,
+        ReportType::Output => {
+            device.write(&report)?;
+        }
     }
+    Ok(())
 }
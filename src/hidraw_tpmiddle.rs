@@ -0,0 +1,100 @@
+use std::sync::Arc;
+use std::time::Instant;
+
+use anyhow::*;
+
+use crate::control::ScrollControl;
+use crate::input::hidraw::EventReader;
+use crate::input::keyboard::ModifierReader;
+use crate::input::Event;
+use crate::output::{self, OutputSink};
+use crate::registry::DeviceModel;
+use crate::tpmiddle::{Action, TPMiddle};
+
+/// Feeds hidraw reports into [`TPMiddle`] and delivers its actions to the output.
+pub struct HidrawTPMiddle {
+    tpmiddle: TPMiddle,
+    control: Box<dyn ScrollControl>,
+    event_reader: EventReader<'static>,
+    output: Arc<dyn OutputSink>,
+}
+
+impl HidrawTPMiddle {
+    pub fn new(
+        model: &'static DeviceModel,
+        tpmiddle: TPMiddle,
+        control: Box<dyn ScrollControl>,
+        output: Arc<dyn OutputSink>,
+    ) -> Result<Self> {
+        Ok(HidrawTPMiddle {
+            tpmiddle,
+            control,
+            event_reader: EventReader::open(model)?,
+            output,
+        })
+    }
+
+    pub fn pollfds(&self) -> impl Iterator<Item = libc::pollfd> + '_ {
+        self.event_reader.pollfds()
+    }
+
+    pub fn rescan(&mut self) -> Result<()> {
+        self.event_reader.rescan()
+    }
+
+    /// Handles the reports of the nodes that are ready in `fds`, the polled result of [`pollfds`].
+    /// `modifiers` is read only if there are any.
+    ///
+    /// [`pollfds`]: Self::pollfds
+    pub fn process_polled(
+        &mut self,
+        fds: &[libc::pollfd],
+        modifiers: &mut ModifierReader,
+    ) -> Result<()> {
+        let events = self.event_reader.read_polled(fds)?;
+        self.process_events(events, modifiers);
+        Ok(())
+    }
+
+    /// Whether [`process_events`] should be fed the pointer motion of the TrackPoint.
+    ///
+    /// [`process_events`]: Self::process_events
+    pub fn captures_motion(&self) -> bool {
+        self.tpmiddle.captures_motion()
+    }
+
+    /// See [`TPMiddle::set_sticky_available`].
+    pub fn set_sticky_available(&mut self, available: bool) {
+        self.tpmiddle.set_sticky_available(available);
+    }
+
+    /// Handles events from elsewhere than hidraw, like the captured pointer motion.
+    pub fn process_events(&mut self, events: Vec<Event>, modifiers: &mut ModifierReader) {
+        if events.is_empty() {
+            return;
+        }
+
+        let now = Instant::now();
+        let modifiers = modifiers.read();
+        for event in events {
+            let actions = self.tpmiddle.handle(now, event, modifiers);
+            self.perform(actions);
+        }
+    }
+
+    /// When [`process_timeout`] should be called, if any.
+    ///
+    /// [`process_timeout`]: Self::process_timeout
+    pub fn deadline(&self) -> Option<Instant> {
+        self.tpmiddle.deadline()
+    }
+
+    pub fn process_timeout(&mut self) {
+        let actions = self.tpmiddle.handle_timeout(Instant::now());
+        self.perform(actions);
+    }
+
+    fn perform(&self, actions: Vec<Action>) {
+        output::perform(actions, &*self.control, &*self.output);
+    }
+}
@@ -1,210 +1,165 @@
+use std::iter;
+use std::str::FromStr;
+
 use anyhow::*;
 use log::*;
-use winapi::ctypes::c_int;
-use winapi::shared::minwindef::{DWORD, LPVOID, UINT};
-use winapi::shared::ntdef::{HANDLE, NULL};
-use winapi::um::winuser::{
-    GetRawInputData, GetRawInputDeviceInfoW, SendInput, HRAWINPUT, INPUT, INPUT_MOUSE,
-    MOUSEEVENTF_MIDDLEDOWN, MOUSEEVENTF_MIDDLEUP, MOUSEEVENTF_XDOWN, MOUSEEVENTF_XUP, RAWHID,
-    RAWINPUT, RAWINPUTHEADER, RIDI_DEVICEINFO, RID_DEVICE_INFO, RID_DEVICE_INFO_HID, RID_INPUT,
-    RIM_TYPEHID,
-};
-
-use crate::hid::DeviceInfo;
-
-pub fn send_click(button: DWORD) {
-    let mut input0: INPUT = Default::default();
-    let mut input1: INPUT = Default::default();
-    input0.type_ = INPUT_MOUSE;
-    input1.type_ = INPUT_MOUSE;
-
-    unsafe {
-        let mi0 = input0.u.mi_mut();
-        let mi1 = input1.u.mi_mut();
-
-        if button == 3 {
-            mi0.dwFlags = MOUSEEVENTF_MIDDLEDOWN;
-            mi1.dwFlags = MOUSEEVENTF_MIDDLEUP;
-        } else {
-            mi0.dwFlags = MOUSEEVENTF_XDOWN;
-            mi0.mouseData = button - 3;
-            mi1.dwFlags = MOUSEEVENTF_XUP;
-            mi1.mouseData = button - 3;
-        }
 
-        let mut input = [input0, input1];
-        SendInput(
-            input.len() as UINT,
-            input.as_mut_ptr(),
-            std::mem::size_of::<INPUT>() as c_int,
-        );
-    }
-}
+use crate::protocol::{InputReport, InputReportIds};
 
-pub fn send_wheel(event: DWORD, mouse_data: DWORD) {
-    let mut input: INPUT = Default::default();
+#[cfg(target_os = "linux")]
+pub mod hidraw;
+#[cfg(target_os = "linux")]
+pub mod keyboard;
+#[cfg(windows)]
+mod raw_input;
+#[cfg(target_os = "linux")]
+pub mod uinput;
 
-    unsafe {
-        input.type_ = INPUT_MOUSE;
-        let mi = input.u.mi_mut();
-        mi.dwFlags = event;
-        mi.mouseData = mouse_data;
+#[cfg(windows)]
+pub use raw_input::{get_hid_device_info, get_modifiers, EventReader, SendInputSink};
 
-        SendInput(1, &mut input, std::mem::size_of::<INPUT>() as c_int);
-    }
-}
+/// Distance of a single wheel notch. Same as `WHEEL_DELTA` of Windows.
+pub const WHEEL_DELTA: i32 = 120;
 
 #[derive(Debug)]
 pub enum Event {
     ButtonDown,
     ButtonUp,
-    Vertical(i8),
-    Horizontal(i8),
+    Scroll(ScrollDelta),
+    /// Pointer motion of the TrackPoint, right and down. Captured only during the sticky scroll.
+    Motion {
+        dx: i32,
+        dy: i32,
+    },
+}
+
+#[derive(Eq, PartialEq, Copy, Clone, Debug)]
+pub enum Axis {
+    Vertical,
+    Horizontal,
 }
 
-pub struct EventReader<'a> {
-    device_filter: &'a [DeviceInfo],
-    buffer: Vec<u8>,
+/// A scroll of the TrackPoint. It moves along either or both of the axes.
+#[derive(Eq, PartialEq, Copy, Clone, Debug, Default)]
+pub struct ScrollDelta {
+    pub dx: i8,
+    pub dy: i8,
 }
 
-impl<'a> EventReader<'a> {
-    pub fn new(device_filter: &'a [DeviceInfo]) -> Self {
-        const HEADROOM: usize = 100;
-        const SIZE: usize = std::mem::size_of::<RAWINPUT>() + HEADROOM;
-        Self {
-            device_filter,
-            buffer: vec![0; SIZE],
+impl ScrollDelta {
+    /// A scroll along the axis only.
+    pub fn along(axis: Axis, delta: i8) -> Self {
+        match axis {
+            Axis::Vertical => Self { dx: 0, dy: delta },
+            Axis::Horizontal => Self { dx: delta, dy: 0 },
         }
     }
-}
 
-impl<'a> EventReader<'a> {
-    fn read_hid(&mut self, l_param: HRAWINPUT) -> Result<RawHID, ()> {
-        unsafe {
-            const SIZE: UINT = std::mem::size_of::<RAWINPUTHEADER>() as _;
-            let mut size = 0;
-            let result =
-                GetRawInputData(l_param as HRAWINPUT, RID_INPUT, NULL as _, &mut size, SIZE);
-            if result == (-1 as i32 as UINT) {
-                return Err(());
-            }
+    pub fn get(&self, axis: Axis) -> i8 {
+        match axis {
+            Axis::Vertical => self.dy,
+            Axis::Horizontal => self.dx,
+        }
+    }
 
-            if self.buffer.len() < size as usize {
-                self.buffer.resize(size as usize, 0);
-            }
-            let result = GetRawInputData(
-                l_param as HRAWINPUT,
-                RID_INPUT,
-                self.buffer.as_mut_ptr() as LPVOID,
-                &mut size,
-                std::mem::size_of::<RAWINPUTHEADER>() as UINT,
-            );
-            if result == (-1 as i32 as UINT) {
-                return Err(());
-            }
-            let raw = self.buffer.as_ptr() as *const RAWINPUT;
-            let header = (*raw).header;
-            if header.dwType != RIM_TYPEHID {
-                return Err(());
-            }
+    pub fn is_zero(&self) -> bool {
+        self.dx == 0 && self.dy == 0
+    }
 
-            let device_info = if let Ok(Some(device_info)) = get_hid_device_info(header.hDevice) {
-                device_info
-            } else {
-                return Err(());
-            };
+    /// The axes that it moves along, vertical first.
+    pub fn axes(&self) -> impl Iterator<Item = (Axis, i8)> {
+        iter::once((Axis::Vertical, self.dy))
+            .chain(iter::once((Axis::Horizontal, self.dx)))
+            .filter(|&(_, delta)| delta != 0)
+    }
+}
 
-            if !self.device_filter.iter().any(|x| *x == device_info) {
-                return Err(());
-            }
+#[derive(Eq, PartialEq, Copy, Clone, Debug)]
+pub enum Button {
+    Left,
+    Middle,
+    X1,
+    X2,
+}
+
+/// A modifier key of the keyboard. Either of the left and right keys.
+#[derive(Eq, PartialEq, Copy, Clone, Debug)]
+pub enum Modifier {
+    Ctrl,
+    Shift,
+    Alt,
+}
 
-            Ok(RawHID::from((*raw).data.hid()))
+impl FromStr for Modifier {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "ctrl" => Ok(Modifier::Ctrl),
+            "shift" => Ok(Modifier::Shift),
+            "alt" => Ok(Modifier::Alt),
+            _ => Err(anyhow!("`{}` is an invalid modifier", s)),
         }
     }
+}
 
-    pub fn read_from_raw_input<'s>(
-        &'s mut self,
-        l_param: HRAWINPUT,
-    ) -> Result<impl Iterator<Item = Event> + 's, ()> {
-        let hid = self.read_hid(l_param)?;
-        let result = hid.iter().filter_map(|packet| {
-            if packet[0] == 0x15 {
-                if packet[2] & 0x04 != 0x00 {
-                    Some(Event::ButtonDown)
-                } else {
-                    Some(Event::ButtonUp)
-                }
-            } else if packet[0] == 0x22 || packet[0] == 0x16 {
-                let dx = packet[1] as i8;
-                let dy = packet[2] as i8;
-                if dx != 0 {
-                    Some(Event::Horizontal(dx))
-                } else if dy != 0 {
-                    Some(Event::Vertical(dy))
-                } else {
-                    warn!("Diagonal is unexpected");
-                    None
-                }
-            } else {
-                warn!("Unexpected packet ID: {:x}", packet[0]);
-                None
-            }
-        });
-        Ok(result)
-    }
+/// The modifier keys held on any keyboard.
+#[derive(Eq, PartialEq, Copy, Clone, Debug, Default)]
+pub struct Modifiers {
+    pub ctrl: bool,
+    pub shift: bool,
+    pub alt: bool,
 }
 
-impl From<&RID_DEVICE_INFO_HID> for DeviceInfo {
-    fn from(di: &RID_DEVICE_INFO_HID) -> Self {
-        Self {
-            vendor_id: di.dwVendorId as u16,
-            product_id: di.dwProductId as u16,
-            usage_page: di.usUsagePage as u16,
-            usage: di.usUsage as u16,
+impl Modifiers {
+    pub fn contains(&self, modifier: Modifier) -> bool {
+        match modifier {
+            Modifier::Ctrl => self.ctrl,
+            Modifier::Shift => self.shift,
+            Modifier::Alt => self.alt,
         }
     }
-}
 
-pub fn get_hid_device_info(handle: HANDLE) -> Result<Option<DeviceInfo>> {
-    let mut rid_device_info: RID_DEVICE_INFO = Default::default();
-    let mut size = std::mem::size_of_val(&rid_device_info) as UINT;
-    rid_device_info.cbSize = size;
-    c_try_ne!(
-        (-1i32) as UINT,
-        GetRawInputDeviceInfoW(
-            handle,
-            RIDI_DEVICEINFO,
-            &mut rid_device_info as *mut RID_DEVICE_INFO as LPVOID,
-            &mut size,
-        )
-    )?;
-
-    if rid_device_info.dwType != RIM_TYPEHID {
-        return Ok(None);
+    pub fn insert(&mut self, modifier: Modifier) {
+        match modifier {
+            Modifier::Ctrl => self.ctrl = true,
+            Modifier::Shift => self.shift = true,
+            Modifier::Alt => self.alt = true,
+        }
     }
-
-    let device_info = unsafe { DeviceInfo::from(rid_device_info.u.hid()) };
-    Ok(Some(device_info))
 }
 
-struct RawHID<'a> {
-    size: usize,
-    buffer: &'a [u8],
-}
+/// `none`, or modifiers joined with `+` like `ctrl+shift`.
+impl FromStr for Modifiers {
+    type Err = anyhow::Error;
 
-impl<'a> From<&'a RAWHID> for RawHID<'a> {
-    fn from(hid: &'a RAWHID) -> Self {
-        let size = hid.dwSizeHid as usize * hid.dwCount as usize;
-        let buffer = unsafe { std::slice::from_raw_parts(hid.bRawData.as_ptr(), size) };
-        Self {
-            size: hid.dwSizeHid as _,
-            buffer,
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut modifiers = Modifiers::default();
+        if s != "none" {
+            for modifier in s.split('+') {
+                modifiers.insert(modifier.trim().parse()?);
+            }
         }
+        Ok(modifiers)
     }
 }
 
-impl<'a> RawHID<'a> {
-    fn iter(&self) -> std::slice::ChunksExact<'a, u8> {
-        self.buffer.chunks_exact(self.size)
+/// Decodes a vendor HID report of the keyboard. The first byte is the report ID.
+pub(crate) fn decode_packet(ids: &InputReportIds, packet: &[u8]) -> Option<Event> {
+    match InputReport::decode(ids, packet) {
+        Ok(InputReport::MiddleButton { pressed: true }) => Some(Event::ButtonDown),
+        Ok(InputReport::MiddleButton { pressed: false }) => Some(Event::ButtonUp),
+        Ok(InputReport::Scroll { dx, dy }) => {
+            let delta = ScrollDelta { dx, dy };
+            if delta.is_zero() {
+                None
+            } else {
+                Some(Event::Scroll(delta))
+            }
+        }
+        Err(err) => {
+            warn!("Unexpected packet: {}", err);
+            None
+        }
     }
 }
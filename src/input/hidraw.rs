@@ -0,0 +1,383 @@
+use std::fs::{self, File};
+use std::io::Read;
+use std::os::unix::io::AsRawFd;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use anyhow::*;
+use log::*;
+
+use crate::hid::DeviceInfo;
+use crate::input::{decode_packet, Event};
+use crate::registry::DeviceModel;
+use crate::util::poll;
+
+const SYSFS_HIDRAW: &str = "/sys/class/hidraw";
+
+/// A hidraw node and the top-level collections of its HID interface.
+///
+/// Unlike Windows, Linux creates a hidraw node per interface rather than per top-level collection,
+/// so a node may match several `DeviceInfo`s.
+#[derive(Debug)]
+pub struct HidrawNode {
+    pub syspath: PathBuf,
+    pub devnode: PathBuf,
+    pub device_infos: Vec<DeviceInfo>,
+}
+
+impl HidrawNode {
+    pub fn from_syspath(syspath: &Path) -> Result<Self> {
+        let syspath = fs::canonicalize(syspath)?;
+        let name = syspath
+            .file_name()
+            .ok_or_else(|| anyhow!("Invalid syspath: {:?}", syspath))?;
+        let uevent = fs::read_to_string(syspath.join("device/uevent"))?;
+        let (vendor_id, product_id) =
+            parse_hid_id(&uevent).ok_or_else(|| anyhow!("Cannot find HID_ID of {:?}", syspath))?;
+        let report_descriptor = fs::read(syspath.join("device/report_descriptor"))?;
+        let device_infos = top_level_collections(&report_descriptor)
+            .into_iter()
+            .map(|(usage_page, usage)| DeviceInfo {
+                vendor_id,
+                product_id,
+                usage_page,
+                usage,
+            })
+            .collect();
+
+        Ok(Self {
+            devnode: Path::new("/dev").join(name),
+            syspath,
+            device_infos,
+        })
+    }
+
+    pub fn matches(&self, device_filter: &[DeviceInfo]) -> bool {
+        self.device_infos.iter().any(|x| device_filter.contains(x))
+    }
+}
+
+pub fn enumerate() -> Result<Vec<HidrawNode>> {
+    let mut nodes = Vec::new();
+    for entry in fs::read_dir(SYSFS_HIDRAW)? {
+        let syspath = entry?.path();
+        match HidrawNode::from_syspath(&syspath) {
+            Ok(node) => nodes.push(node),
+            Err(err) => {
+                // The device is spuriously disconnected while enumerating
+                debug!("Error while reading {:?}: {}", syspath, err);
+            }
+        }
+    }
+    Ok(nodes)
+}
+
+/// Parses `HID_ID=0003:000017EF:000060EE` line of the uevent.
+fn parse_hid_id(uevent: &str) -> Option<(u16, u16)> {
+    let hid_id = uevent
+        .lines()
+        .find_map(|line| line.strip_prefix("HID_ID="))?;
+    let mut parts = hid_id.split(':').skip(1);
+    let vendor_id = u32::from_str_radix(parts.next()?, 16).ok()?;
+    let product_id = u32::from_str_radix(parts.next()?, 16).ok()?;
+    Some((vendor_id as u16, product_id as u16))
+}
+
+/// Collects `(usage_page, usage)` of the top-level collections in a HID report descriptor.
+fn top_level_collections(descriptor: &[u8]) -> Vec<(u16, u16)> {
+    const TYPE_MAIN: u8 = 0;
+    const TYPE_GLOBAL: u8 = 1;
+    const TYPE_LOCAL: u8 = 2;
+
+    const MAIN_COLLECTION: u8 = 0xA;
+    const MAIN_END_COLLECTION: u8 = 0xC;
+    const GLOBAL_USAGE_PAGE: u8 = 0x0;
+    const GLOBAL_PUSH: u8 = 0xA;
+    const GLOBAL_POP: u8 = 0xB;
+    const LOCAL_USAGE: u8 = 0x0;
+
+    let mut result = Vec::new();
+    let mut usage_page = 0u16;
+    let mut usage_page_stack = Vec::new();
+    let mut usage = None;
+    let mut depth = 0usize;
+
+    let mut i = 0;
+    while i < descriptor.len() {
+        let prefix = descriptor[i];
+        if prefix == 0xFE {
+            // Long item. No long item tags are defined, so just skip it.
+            let size = descriptor.get(i + 1).copied().unwrap_or(0) as usize;
+            i += 3 + size;
+            continue;
+        }
+
+        let size = match prefix & 0x03 {
+            3 => 4,
+            size => size as usize,
+        };
+        let item_type = (prefix >> 2) & 0x03;
+        let tag = prefix >> 4;
+        let data = match descriptor.get(i + 1..i + 1 + size) {
+            Some(data) => data
+                .iter()
+                .rev()
+                .fold(0u32, |acc, byte| (acc << 8) | *byte as u32),
+            None => break,
+        };
+        i += 1 + size;
+
+        match (item_type, tag) {
+            (TYPE_MAIN, MAIN_COLLECTION) => {
+                if depth == 0 {
+                    let (page, id) = match usage {
+                        // Extended usage contains its own usage page.
+                        Some((4, usage)) => ((usage >> 16) as u16, usage as u16),
+                        Some((_, usage)) => (usage_page, usage as u16),
+                        None => (usage_page, 0),
+                    };
+                    result.push((page, id));
+                }
+                depth += 1;
+            }
+            (TYPE_MAIN, MAIN_END_COLLECTION) => depth = depth.saturating_sub(1),
+            (TYPE_GLOBAL, GLOBAL_USAGE_PAGE) => usage_page = data as u16,
+            (TYPE_GLOBAL, GLOBAL_PUSH) => usage_page_stack.push(usage_page),
+            (TYPE_GLOBAL, GLOBAL_POP) => usage_page = usage_page_stack.pop().unwrap_or(0),
+            (TYPE_LOCAL, LOCAL_USAGE) if usage.is_none() => usage = Some((size, data)),
+            _ => {}
+        }
+
+        if item_type == TYPE_MAIN {
+            // Local items only affect the next main item.
+            usage = None;
+        }
+    }
+
+    result
+}
+
+/// Reads vendor reports from the hidraw nodes of the model.
+pub struct EventReader<'a> {
+    model: &'a DeviceModel,
+    device_filter: Vec<DeviceInfo>,
+    files: Vec<File>,
+    buffer: Vec<u8>,
+}
+
+impl<'a> EventReader<'a> {
+    pub fn open(model: &'a DeviceModel) -> Result<Self> {
+        const MAX_REPORT_SIZE: usize = 64;
+        let mut reader = Self {
+            model,
+            device_filter: model.input_device_infos(),
+            files: Vec::new(),
+            buffer: vec![0; MAX_REPORT_SIZE],
+        };
+        reader.rescan()?;
+        Ok(reader)
+    }
+
+    /// Reopens the matching hidraw nodes. A device might have appeared one node at a time.
+    pub fn rescan(&mut self) -> Result<()> {
+        let mut files = Vec::new();
+        for node in enumerate()? {
+            if !node.matches(&self.device_filter) {
+                continue;
+            }
+
+            trace!("Opening {:?}: {:?}", node.devnode, node.device_infos);
+            let file =
+                File::open(&node.devnode).with_context(|| format!("opening {:?}", node.devnode))?;
+            files.push(file);
+        }
+
+        if files.is_empty() {
+            bail!("Cannot find hidraw nodes");
+        }
+
+        self.files = files;
+        Ok(())
+    }
+
+    pub fn pollfds(&self) -> impl Iterator<Item = libc::pollfd> + '_ {
+        self.files.iter().map(|file| libc::pollfd {
+            fd: file.as_raw_fd(),
+            events: libc::POLLIN,
+            revents: 0,
+        })
+    }
+
+    /// Waits for reports up to `timeout`, or indefinitely if `None`, then decodes them.
+    pub fn read(&mut self, timeout: Option<Duration>) -> Result<Vec<Event>> {
+        let mut fds: Vec<_> = self.pollfds().collect();
+        if !poll(&mut fds, timeout)? {
+            return Ok(Vec::new());
+        }
+        self.read_polled(&fds)
+    }
+
+    /// Decodes the reports of the nodes that are ready in `fds`, the polled result of [`pollfds`].
+    ///
+    /// [`pollfds`]: Self::pollfds
+    pub fn read_polled(&mut self, fds: &[libc::pollfd]) -> Result<Vec<Event>> {
+        let mut events = Vec::new();
+        let mut disconnected = Vec::new();
+        for (index, (fd, file)) in fds.iter().zip(self.files.iter_mut()).enumerate() {
+            if fd.revents & (libc::POLLERR | libc::POLLHUP | libc::POLLNVAL) != 0 {
+                disconnected.push(index);
+                continue;
+            }
+            if fd.revents & libc::POLLIN == 0 {
+                continue;
+            }
+
+            let size = file.read(&mut self.buffer)?;
+            if size == 0 {
+                continue;
+            }
+            events.extend(decode_packet(
+                &self.model.input_report_ids,
+                &self.buffer[..size],
+            ));
+        }
+
+        for index in disconnected.into_iter().rev() {
+            // The removal will be notified separately. Stop polling it not to spin.
+            debug!("The hidraw node is disconnected");
+            self.files.remove(index);
+        }
+
+        Ok(events)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const UEVENT: &str = "DRIVER=hid-generic\n\
+                          HID_ID=0003:000017EF:000060EE\n\
+                          HID_NAME=Lenovo TrackPoint Keyboard II\n\
+                          HID_PHYS=usb-0000:00:14.0-2/input1\n\
+                          MODALIAS=hid:b0003g0001v000017EFp000060EE\n";
+
+    /// Laid out like the mouse interface of the TrackPoint Keyboard II USB receiver: a mouse, a
+    /// consumer control and the vendor collections of the middle button and the scroll.
+    const DESCRIPTOR: &[u8] = &[
+        0x05, 0x01, // Usage Page (Generic Desktop)
+        0x09, 0x02, // Usage (Mouse)
+        0xA1, 0x01, // Collection (Application)
+        0x85, 0x01, //   Report ID (1)
+        0x09, 0x01, //   Usage (Pointer)
+        0xA1, 0x00, //   Collection (Physical)
+        0x05, 0x09, //     Usage Page (Button)
+        0x19, 0x01, //     Usage Minimum (1)
+        0x29, 0x03, //     Usage Maximum (3)
+        0x15, 0x00, //     Logical Minimum (0)
+        0x25, 0x01, //     Logical Maximum (1)
+        0x95, 0x03, //     Report Count (3)
+        0x75, 0x01, //     Report Size (1)
+        0x81, 0x02, //     Input (Data, Variable, Absolute)
+        0x95, 0x01, //     Report Count (1)
+        0x75, 0x05, //     Report Size (5)
+        0x81, 0x03, //     Input (Constant)
+        0x05, 0x01, //     Usage Page (Generic Desktop)
+        0x09, 0x30, //     Usage (X)
+        0x09, 0x31, //     Usage (Y)
+        0x09, 0x38, //     Usage (Wheel)
+        0x15, 0x81, //     Logical Minimum (-127)
+        0x25, 0x7F, //     Logical Maximum (127)
+        0x75, 0x08, //     Report Size (8)
+        0x95, 0x03, //     Report Count (3)
+        0x81, 0x06, //     Input (Data, Variable, Relative)
+        0xC0, //         End Collection
+        0xC0, //       End Collection
+        0x05, 0x0C, // Usage Page (Consumer)
+        0x09, 0x01, // Usage (Consumer Control)
+        0xA1, 0x01, // Collection (Application)
+        0x85, 0x03, //   Report ID (3)
+        0x19, 0x00, //   Usage Minimum (0)
+        0x2A, 0x3C, 0x02, // Usage Maximum (0x023C)
+        0x15, 0x00, //   Logical Minimum (0)
+        0x26, 0x3C, 0x02, // Logical Maximum (0x023C)
+        0x95, 0x01, //   Report Count (1)
+        0x75, 0x10, //   Report Size (16)
+        0x81, 0x00, //   Input (Data, Array)
+        0xC0, //       End Collection
+        0xA4, //       Push
+        0x06, 0xA0, 0xFF, // Usage Page (0xFFA0)
+        0x09, 0x01, // Usage (1)
+        0xA1, 0x01, // Collection (Application)
+        0x85, 0x15, //   Report ID (21)
+        0x09, 0x02, //   Usage (2)
+        0x75, 0x08, //   Report Size (8)
+        0x95, 0x02, //   Report Count (2)
+        0x81, 0x02, //   Input (Data, Variable, Absolute)
+        0xC0, //       End Collection
+        0xB4, //       Pop
+        0x0B, 0x01, 0x00, 0x10, 0xFF, // Usage (0xFF10:0x0001)
+        0xA1, 0x01, // Collection (Application)
+        0x85, 0x16, //   Report ID (22)
+        0x06, 0x10, 0xFF, // Usage Page (0xFF10)
+        0x09, 0x02, //   Usage (2)
+        0x75, 0x08, //   Report Size (8)
+        0x95, 0x02, //   Report Count (2)
+        0x81, 0x06, //   Input (Data, Variable, Relative)
+        0xC0, //       End Collection
+    ];
+
+    const COLLECTIONS: [(u16, u16); 4] =
+        [(0x01, 0x02), (0x0C, 0x01), (0xFFA0, 0x01), (0xFF10, 0x01)];
+
+    #[test]
+    fn hid_id() {
+        assert_eq!(parse_hid_id(UEVENT), Some((0x17EF, 0x60EE)));
+        assert_eq!(
+            parse_hid_id("HID_ID=0005:000017EF:000060E1"),
+            Some((0x17EF, 0x60E1))
+        );
+    }
+
+    #[test]
+    fn invalid_hid_id() {
+        assert_eq!(parse_hid_id("DRIVER=hid-generic\n"), None);
+        assert_eq!(parse_hid_id("HID_ID=0003:000017EF\n"), None);
+        assert_eq!(parse_hid_id("HID_ID=0003:000017EF:0000XXXX\n"), None);
+        // The prefix must start the line.
+        assert_eq!(parse_hid_id("X_HID_ID=0003:000017EF:000060EE\n"), None);
+    }
+
+    #[test]
+    fn collections() {
+        assert_eq!(top_level_collections(DESCRIPTOR), COLLECTIONS);
+    }
+
+    #[test]
+    fn truncated_descriptor() {
+        assert_eq!(top_level_collections(&[]), []);
+        // Cut in the middle of the data of the vendor Usage Page.
+        let end = DESCRIPTOR.iter().position(|byte| *byte == 0xA0).unwrap();
+        assert_eq!(top_level_collections(&DESCRIPTOR[..end]), COLLECTIONS[..2]);
+        // Cut right after the vendor Collection item.
+        let end = end + 6;
+        assert_eq!(DESCRIPTOR[end - 2], 0xA1);
+        assert_eq!(top_level_collections(&DESCRIPTOR[..end]), COLLECTIONS[..3]);
+    }
+
+    #[test]
+    fn long_items_are_skipped() {
+        let mut descriptor = vec![
+            0xFE, 0x03, 0xF0, 0xA1, 0x01, 0xC0, // Long item whose data looks like a collection
+        ];
+        descriptor.extend_from_slice(DESCRIPTOR);
+        descriptor.extend_from_slice(&[0xFE, 0x02, 0xF1, 0xA1, 0x01]);
+        assert_eq!(top_level_collections(&descriptor), COLLECTIONS);
+
+        // A long item running past the end stops the parse without panicking.
+        descriptor.extend_from_slice(&[0xFE, 0xFF, 0xF2, 0xA1]);
+        assert_eq!(top_level_collections(&descriptor), COLLECTIONS);
+        descriptor.push(0xFE);
+        assert_eq!(top_level_collections(&descriptor), COLLECTIONS);
+    }
+}
@@ -0,0 +1,102 @@
+use std::fs::{self, File, OpenOptions};
+use std::os::unix::fs::OpenOptionsExt;
+use std::os::unix::io::AsRawFd;
+use std::path::{Path, PathBuf};
+
+use anyhow::*;
+use log::*;
+
+use crate::input::Modifiers;
+use crate::util::{has_bit, is_event_node, SYSFS_INPUT};
+
+const KEY_MAX: usize = 0x2ff;
+/// `EVIOCGKEY` for a buffer of `KEY_MAX` bits.
+const EVIOCGKEY: libc::c_ulong = 0x8060_4518;
+
+const KEY_LEFTCTRL: usize = 29;
+const KEY_LEFTSHIFT: usize = 42;
+const KEY_RIGHTSHIFT: usize = 54;
+const KEY_LEFTALT: usize = 56;
+const KEY_RIGHTCTRL: usize = 97;
+const KEY_RIGHTALT: usize = 100;
+
+const KEY_A: usize = 30;
+
+/// Queries the modifier keys held on every keyboard from the key state of their event nodes.
+/// It never reads nor grabs their events.
+/// Counterpart of `GetAsyncKeyState` of Windows.
+pub struct ModifierReader {
+    keyboards: Vec<(PathBuf, File)>,
+}
+
+impl ModifierReader {
+    pub fn new() -> Result<Self> {
+        let mut reader = Self {
+            keyboards: Vec::new(),
+        };
+        reader.rescan()?;
+        Ok(reader)
+    }
+
+    pub fn rescan(&mut self) -> Result<()> {
+        self.keyboards.clear();
+        for entry in fs::read_dir(SYSFS_INPUT)? {
+            let syspath = entry?.path();
+            match is_keyboard(&syspath) {
+                Ok(true) => {}
+                Ok(false) => continue,
+                Err(err) => {
+                    // The device is spuriously disconnected while enumerating
+                    debug!("Error while reading {:?}: {}", syspath, err);
+                    continue;
+                }
+            }
+
+            let devnode = Path::new("/dev/input").join(syspath.file_name().unwrap());
+            match OpenOptions::new()
+                .read(true)
+                .custom_flags(libc::O_NONBLOCK)
+                .open(&devnode)
+            {
+                Ok(file) => {
+                    trace!("ModifierReader reads: {:?}", devnode);
+                    self.keyboards.push((devnode, file));
+                }
+                Err(err) => debug!("Cannot open {:?}: {}", devnode, err),
+            }
+        }
+        Ok(())
+    }
+
+    /// The modifier keys held now.
+    pub fn read(&mut self) -> Modifiers {
+        let mut modifiers = Modifiers::default();
+        self.keyboards.retain(|(devnode, file)| {
+            let mut keys = [0u8; KEY_MAX / 8 + 1];
+            let result =
+                unsafe { libc::ioctl(file.as_raw_fd(), EVIOCGKEY as _, keys.as_mut_ptr()) };
+            if result < 0 {
+                // It will be opened again when it comes back.
+                debug!("The keyboard is disconnected: {:?}", devnode);
+                return false;
+            }
+
+            let pressed = |key: usize| keys[key / 8] & (1 << (key % 8)) != 0;
+            modifiers.ctrl |= pressed(KEY_LEFTCTRL) || pressed(KEY_RIGHTCTRL);
+            modifiers.shift |= pressed(KEY_LEFTSHIFT) || pressed(KEY_RIGHTSHIFT);
+            modifiers.alt |= pressed(KEY_LEFTALT) || pressed(KEY_RIGHTALT);
+            true
+        });
+        modifiers
+    }
+}
+
+/// Tests if the input device of `syspath` is an event node of a keyboard.
+pub fn is_keyboard(syspath: &Path) -> Result<bool> {
+    if !is_event_node(syspath) {
+        return Ok(false);
+    }
+
+    let keys = fs::read_to_string(syspath.join("device").join("capabilities/key"))?;
+    Ok(has_bit(&keys, KEY_A))
+}
@@ -0,0 +1,239 @@
+use anyhow::*;
+use winapi::ctypes::c_int;
+use winapi::shared::minwindef::{DWORD, LPVOID, UINT};
+use winapi::shared::ntdef::{HANDLE, NULL};
+use winapi::um::winuser::{
+    GetAsyncKeyState, GetRawInputData, GetRawInputDeviceInfoW, SendInput, HRAWINPUT, INPUT,
+    INPUT_MOUSE, MOUSEEVENTF_HWHEEL, MOUSEEVENTF_LEFTDOWN, MOUSEEVENTF_LEFTUP,
+    MOUSEEVENTF_MIDDLEDOWN, MOUSEEVENTF_MIDDLEUP, MOUSEEVENTF_MOVE, MOUSEEVENTF_WHEEL,
+    MOUSEEVENTF_XDOWN, MOUSEEVENTF_XUP, RAWHID, RAWINPUT, RAWINPUTHEADER, RIDI_DEVICEINFO,
+    RID_DEVICE_INFO, RID_DEVICE_INFO_HID, RID_INPUT, RIM_TYPEHID, VK_CONTROL, VK_MENU, VK_SHIFT,
+    XBUTTON1, XBUTTON2,
+};
+
+use crate::hid::DeviceInfo;
+use crate::input::{decode_packet, Axis, Button, Event, Modifiers};
+use crate::output::OutputSink;
+use crate::registry::DeviceModel;
+
+fn button_input(button: Button, pressed: bool) -> INPUT {
+    let mut input: INPUT = Default::default();
+    input.type_ = INPUT_MOUSE;
+
+    unsafe {
+        let mi = input.u.mi_mut();
+        let (down, up, xbutton) = match button {
+            Button::Left => (MOUSEEVENTF_LEFTDOWN, MOUSEEVENTF_LEFTUP, 0),
+            Button::Middle => (MOUSEEVENTF_MIDDLEDOWN, MOUSEEVENTF_MIDDLEUP, 0),
+            Button::X1 => (MOUSEEVENTF_XDOWN, MOUSEEVENTF_XUP, XBUTTON1),
+            Button::X2 => (MOUSEEVENTF_XDOWN, MOUSEEVENTF_XUP, XBUTTON2),
+        };
+        mi.dwFlags = if pressed { down } else { up };
+        mi.mouseData = xbutton as DWORD;
+    }
+    input
+}
+
+fn send_inputs(input: &mut [INPUT]) {
+    unsafe {
+        SendInput(
+            input.len() as UINT,
+            input.as_mut_ptr(),
+            std::mem::size_of::<INPUT>() as c_int,
+        );
+    }
+}
+
+fn send_click(button: Button) {
+    send_inputs(&mut [button_input(button, true), button_input(button, false)]);
+}
+
+fn send_motion(dx: i32, dy: i32) {
+    let mut input: INPUT = Default::default();
+    input.type_ = INPUT_MOUSE;
+
+    unsafe {
+        let mi = input.u.mi_mut();
+        mi.dwFlags = MOUSEEVENTF_MOVE;
+        mi.dx = dx;
+        mi.dy = dy;
+    }
+    send_inputs(&mut [input]);
+}
+
+fn send_wheel(axis: Axis, mouse_data: i32) {
+    let mut input: INPUT = Default::default();
+
+    unsafe {
+        input.type_ = INPUT_MOUSE;
+        let mi = input.u.mi_mut();
+        mi.dwFlags = match axis {
+            Axis::Vertical => MOUSEEVENTF_WHEEL,
+            Axis::Horizontal => MOUSEEVENTF_HWHEEL,
+        };
+        mi.mouseData = mouse_data as DWORD;
+
+        SendInput(1, &mut input, std::mem::size_of::<INPUT>() as c_int);
+    }
+}
+
+/// Emits the events with `SendInput`.
+pub struct SendInputSink;
+
+impl OutputSink for SendInputSink {
+    fn send_wheel(&self, axis: Axis, mouse_data: i32) {
+        send_wheel(axis, mouse_data);
+    }
+
+    fn send_click(&self, button: Button) {
+        send_click(button);
+    }
+
+    fn send_button(&self, button: Button, pressed: bool) {
+        send_inputs(&mut [button_input(button, pressed)]);
+    }
+
+    fn send_motion(&self, dx: i32, dy: i32) {
+        send_motion(dx, dy);
+    }
+}
+
+/// The modifier keys held now, regardless of the focused window.
+pub fn get_modifiers() -> Modifiers {
+    let pressed = |key| unsafe { GetAsyncKeyState(key) } as u16 & 0x8000 != 0;
+    Modifiers {
+        ctrl: pressed(VK_CONTROL),
+        shift: pressed(VK_SHIFT),
+        alt: pressed(VK_MENU),
+    }
+}
+
+pub struct EventReader<'a> {
+    model: &'a DeviceModel,
+    device_filter: Vec<DeviceInfo>,
+    buffer: Vec<u8>,
+}
+
+impl<'a> EventReader<'a> {
+    pub fn new(model: &'a DeviceModel) -> Self {
+        const HEADROOM: usize = 100;
+        const SIZE: usize = std::mem::size_of::<RAWINPUT>() + HEADROOM;
+        Self {
+            model,
+            device_filter: model.input_device_infos(),
+            buffer: vec![0; SIZE],
+        }
+    }
+}
+
+impl<'a> EventReader<'a> {
+    fn read_hid(&mut self, l_param: HRAWINPUT) -> Result<RawHID, ()> {
+        unsafe {
+            const SIZE: UINT = std::mem::size_of::<RAWINPUTHEADER>() as _;
+            let mut size = 0;
+            let result =
+                GetRawInputData(l_param as HRAWINPUT, RID_INPUT, NULL as _, &mut size, SIZE);
+            if result == (-1 as i32 as UINT) {
+                return Err(());
+            }
+
+            if self.buffer.len() < size as usize {
+                self.buffer.resize(size as usize, 0);
+            }
+            let result = GetRawInputData(
+                l_param as HRAWINPUT,
+                RID_INPUT,
+                self.buffer.as_mut_ptr() as LPVOID,
+                &mut size,
+                std::mem::size_of::<RAWINPUTHEADER>() as UINT,
+            );
+            if result == (-1 as i32 as UINT) {
+                return Err(());
+            }
+            let raw = self.buffer.as_ptr() as *const RAWINPUT;
+            let header = (*raw).header;
+            if header.dwType != RIM_TYPEHID {
+                return Err(());
+            }
+
+            let device_info = if let Ok(Some(device_info)) = get_hid_device_info(header.hDevice) {
+                device_info
+            } else {
+                return Err(());
+            };
+
+            if !self.device_filter.iter().any(|x| *x == device_info) {
+                return Err(());
+            }
+
+            Ok(RawHID::from((*raw).data.hid()))
+        }
+    }
+
+    pub fn read_from_raw_input<'s>(
+        &'s mut self,
+        l_param: HRAWINPUT,
+    ) -> Result<impl Iterator<Item = Event> + 's, ()> {
+        let model: &'s DeviceModel = self.model;
+        let hid = self.read_hid(l_param)?;
+        let result = hid
+            .iter()
+            .filter_map(move |packet| decode_packet(&model.input_report_ids, packet));
+        Ok(result)
+    }
+}
+
+impl From<&RID_DEVICE_INFO_HID> for DeviceInfo {
+    fn from(di: &RID_DEVICE_INFO_HID) -> Self {
+        Self {
+            vendor_id: di.dwVendorId as u16,
+            product_id: di.dwProductId as u16,
+            usage_page: di.usUsagePage as u16,
+            usage: di.usUsage as u16,
+        }
+    }
+}
+
+pub fn get_hid_device_info(handle: HANDLE) -> Result<Option<DeviceInfo>> {
+    let mut rid_device_info: RID_DEVICE_INFO = Default::default();
+    let mut size = std::mem::size_of_val(&rid_device_info) as UINT;
+    rid_device_info.cbSize = size;
+    c_try_ne!(
+        (-1i32) as UINT,
+        GetRawInputDeviceInfoW(
+            handle,
+            RIDI_DEVICEINFO,
+            &mut rid_device_info as *mut RID_DEVICE_INFO as LPVOID,
+            &mut size,
+        )
+    )?;
+
+    if rid_device_info.dwType != RIM_TYPEHID {
+        return Ok(None);
+    }
+
+    let device_info = unsafe { DeviceInfo::from(rid_device_info.u.hid()) };
+    Ok(Some(device_info))
+}
+
+struct RawHID<'a> {
+    size: usize,
+    buffer: &'a [u8],
+}
+
+impl<'a> From<&'a RAWHID> for RawHID<'a> {
+    fn from(hid: &'a RAWHID) -> Self {
+        let size = hid.dwSizeHid as usize * hid.dwCount as usize;
+        let buffer = unsafe { std::slice::from_raw_parts(hid.bRawData.as_ptr(), size) };
+        Self {
+            size: hid.dwSizeHid as _,
+            buffer,
+        }
+    }
+}
+
+impl<'a> RawHID<'a> {
+    fn iter(&self) -> std::slice::ChunksExact<'a, u8> {
+        self.buffer.chunks_exact(self.size)
+    }
+}
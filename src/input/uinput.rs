@@ -0,0 +1,283 @@
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::os::unix::fs::OpenOptionsExt;
+use std::os::unix::io::AsRawFd;
+use std::sync::Mutex;
+
+use anyhow::*;
+use log::*;
+
+use crate::input::{Axis, Button, WHEEL_DELTA};
+use crate::output::OutputSink;
+
+const UI_DEV_CREATE: libc::c_ulong = 0x5501;
+const UI_DEV_DESTROY: libc::c_ulong = 0x5502;
+const UI_DEV_SETUP: libc::c_ulong = 0x405c_5503;
+const UI_SET_EVBIT: libc::c_ulong = 0x4004_5564;
+const UI_SET_KEYBIT: libc::c_ulong = 0x4004_5565;
+const UI_SET_RELBIT: libc::c_ulong = 0x4004_5566;
+
+const BUS_VIRTUAL: u16 = 0x06;
+
+const EV_SYN: u16 = 0x00;
+const EV_KEY: u16 = 0x01;
+const EV_REL: u16 = 0x02;
+
+const SYN_REPORT: u16 = 0x00;
+
+const REL_X: u16 = 0x00;
+const REL_Y: u16 = 0x01;
+const REL_HWHEEL: u16 = 0x06;
+const REL_WHEEL: u16 = 0x08;
+const REL_WHEEL_HI_RES: u16 = 0x0b;
+const REL_HWHEEL_HI_RES: u16 = 0x0c;
+
+const BTN_LEFT: u16 = 0x110;
+const BTN_RIGHT: u16 = 0x111;
+const BTN_MIDDLE: u16 = 0x112;
+const BTN_SIDE: u16 = 0x113;
+const BTN_EXTRA: u16 = 0x114;
+
+#[repr(C)]
+struct UInputSetup {
+    id: libc::input_id,
+    name: [u8; 80],
+    ff_effects_max: u32,
+}
+
+/// A uinput virtual pointer that emits clicks, drags and high-resolution wheel events.
+///
+/// `REL_WHEEL_HI_RES` shares the unit with `WHEEL_DELTA`, so sub-notch wheel values are sent as is.
+/// Legacy `REL_WHEEL` is sent when the accumulated value reaches a full notch.
+pub struct VirtualPointer {
+    file: File,
+    notches: Mutex<[NotchAccumulator; 2]>,
+}
+
+impl VirtualPointer {
+    pub fn new(name: &str) -> Result<Self> {
+        let file = OpenOptions::new()
+            .write(true)
+            .custom_flags(libc::O_NONBLOCK)
+            .open("/dev/uinput")
+            .context("opening /dev/uinput")?;
+        let fd = file.as_raw_fd();
+
+        ioctl(fd, UI_SET_EVBIT, EV_KEY as _)?;
+        for &key in &[BTN_LEFT, BTN_RIGHT, BTN_MIDDLE, BTN_SIDE, BTN_EXTRA] {
+            ioctl(fd, UI_SET_KEYBIT, key as _)?;
+        }
+        // REL_X and REL_Y are required to be recognized as a mouse.
+        ioctl(fd, UI_SET_EVBIT, EV_REL as _)?;
+        for &rel in &[
+            REL_X,
+            REL_Y,
+            REL_WHEEL,
+            REL_HWHEEL,
+            REL_WHEEL_HI_RES,
+            REL_HWHEEL_HI_RES,
+        ] {
+            ioctl(fd, UI_SET_RELBIT, rel as _)?;
+        }
+
+        let mut setup = UInputSetup {
+            id: libc::input_id {
+                bustype: BUS_VIRTUAL,
+                vendor: 0,
+                product: 0,
+                version: 0,
+            },
+            name: [0; 80],
+            ff_effects_max: 0,
+        };
+        let len = name.len().min(setup.name.len() - 1);
+        setup.name[..len].copy_from_slice(&name.as_bytes()[..len]);
+        ioctl(fd, UI_DEV_SETUP, &setup as *const UInputSetup as _)?;
+        ioctl(fd, UI_DEV_CREATE, 0)?;
+
+        Ok(Self {
+            file,
+            notches: Mutex::new(Default::default()),
+        })
+    }
+
+    pub fn send_click(&self, button: Button) {
+        let code = button_code(button);
+        self.emit(&[
+            event(EV_KEY, code, 1),
+            event(EV_SYN, SYN_REPORT, 0),
+            event(EV_KEY, code, 0),
+            event(EV_SYN, SYN_REPORT, 0),
+        ]);
+    }
+
+    pub fn send_button(&self, button: Button, pressed: bool) {
+        self.emit(&[
+            event(EV_KEY, button_code(button), pressed as i32),
+            event(EV_SYN, SYN_REPORT, 0),
+        ]);
+    }
+
+    pub fn send_motion(&self, dx: i32, dy: i32) {
+        self.emit(&[
+            event(EV_REL, REL_X, dx),
+            event(EV_REL, REL_Y, dy),
+            event(EV_SYN, SYN_REPORT, 0),
+        ]);
+    }
+
+    pub fn send_wheel(&self, axis: Axis, mouse_data: i32) {
+        let (index, hi_res, legacy) = match axis {
+            Axis::Vertical => (0, REL_WHEEL_HI_RES, REL_WHEEL),
+            Axis::Horizontal => (1, REL_HWHEEL_HI_RES, REL_HWHEEL),
+        };
+
+        let notches = self.notches.lock().unwrap()[index].add(mouse_data);
+
+        if notches != 0 {
+            self.emit(&[
+                event(EV_REL, hi_res, mouse_data),
+                event(EV_REL, legacy, notches),
+                event(EV_SYN, SYN_REPORT, 0),
+            ]);
+        } else {
+            self.emit(&[
+                event(EV_REL, hi_res, mouse_data),
+                event(EV_SYN, SYN_REPORT, 0),
+            ]);
+        }
+    }
+
+    /// Emits raw events. The kernel drops the events that the virtual pointer doesn't support.
+    pub fn emit(&self, events: &[libc::input_event]) {
+        let bytes = unsafe {
+            std::slice::from_raw_parts(events.as_ptr() as *const u8, std::mem::size_of_val(events))
+        };
+        if let Err(err) = (&self.file).write_all(bytes) {
+            warn!("Cannot write to uinput: {}", err);
+        }
+    }
+}
+
+impl OutputSink for VirtualPointer {
+    fn send_wheel(&self, axis: Axis, mouse_data: i32) {
+        VirtualPointer::send_wheel(self, axis, mouse_data);
+    }
+
+    fn send_click(&self, button: Button) {
+        VirtualPointer::send_click(self, button);
+    }
+
+    fn send_button(&self, button: Button, pressed: bool) {
+        VirtualPointer::send_button(self, button, pressed);
+    }
+
+    fn send_motion(&self, dx: i32, dy: i32) {
+        VirtualPointer::send_motion(self, dx, dy);
+    }
+}
+
+impl Drop for VirtualPointer {
+    fn drop(&mut self) {
+        if let Err(err) = ioctl(self.file.as_raw_fd(), UI_DEV_DESTROY, 0) {
+            warn!("Cannot destroy the uinput device: {}", err);
+        }
+    }
+}
+
+/// Accumulates high-resolution wheel values into whole notches of legacy `REL_WHEEL`.
+#[derive(Debug, Default)]
+struct NotchAccumulator {
+    remainder: i32,
+}
+
+impl NotchAccumulator {
+    /// Adds `mouse_data` and returns the notches completed by it.
+    fn add(&mut self, mouse_data: i32) -> i32 {
+        if self.remainder.signum() * mouse_data.signum() < 0 {
+            // Direction is changed. Discard the partial notch.
+            self.remainder = 0;
+        }
+        self.remainder += mouse_data;
+        let notches = self.remainder / WHEEL_DELTA;
+        self.remainder -= notches * WHEEL_DELTA;
+        notches
+    }
+}
+
+fn button_code(button: Button) -> u16 {
+    match button {
+        Button::Left => BTN_LEFT,
+        Button::Middle => BTN_MIDDLE,
+        Button::X1 => BTN_SIDE,
+        Button::X2 => BTN_EXTRA,
+    }
+}
+
+fn event(type_: u16, code: u16, value: i32) -> libc::input_event {
+    libc::input_event {
+        time: libc::timeval {
+            tv_sec: 0,
+            tv_usec: 0,
+        },
+        type_,
+        code,
+        value,
+    }
+}
+
+fn ioctl(fd: libc::c_int, request: libc::c_ulong, arg: libc::c_ulong) -> Result<()> {
+    let result = unsafe { libc::ioctl(fd, request as _, arg) };
+    if result < 0 {
+        return Err(std::io::Error::last_os_error())
+            .with_context(|| format!("ioctl {:x}", request));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn add_all(accumulator: &mut NotchAccumulator, values: &[i32]) -> Vec<i32> {
+        values.iter().map(|value| accumulator.add(*value)).collect()
+    }
+
+    #[test]
+    fn whole_notches() {
+        let mut accumulator = NotchAccumulator::default();
+        assert_eq!(
+            add_all(
+                &mut accumulator,
+                &[WHEEL_DELTA, -WHEEL_DELTA, 3 * WHEEL_DELTA]
+            ),
+            [1, -1, 3]
+        );
+        assert_eq!(accumulator.remainder, 0);
+    }
+
+    #[test]
+    fn remainder_carries_over() {
+        let mut accumulator = NotchAccumulator::default();
+        assert_eq!(
+            add_all(&mut accumulator, &[40, 40, 40, 40, 40, 40]),
+            [0, 0, 1, 0, 0, 1]
+        );
+        assert_eq!(accumulator.remainder, 0);
+        assert_eq!(add_all(&mut accumulator, &[-100, -100, -100]), [0, -1, -1]);
+        assert_eq!(accumulator.remainder, -60);
+        // A single value can complete several notches and keep the rest.
+        let mut accumulator = NotchAccumulator::default();
+        assert_eq!(accumulator.add(2 * WHEEL_DELTA + 30), 2);
+        assert_eq!(accumulator.add(WHEEL_DELTA - 30), 1);
+    }
+
+    #[test]
+    fn direction_change_discards_partial_notch() {
+        let mut accumulator = NotchAccumulator::default();
+        assert_eq!(add_all(&mut accumulator, &[100, -100, -20]), [0, 0, -1]);
+        assert_eq!(add_all(&mut accumulator, &[-100, 100, 20]), [0, 0, 1]);
+        // Zero keeps the partial notch.
+        assert_eq!(add_all(&mut accumulator, &[100, 0, 20]), [0, 0, 1]);
+    }
+}
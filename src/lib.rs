@@ -4,16 +4,40 @@ extern crate lazy_static;
 #[macro_use]
 mod util;
 mod args;
+#[cfg(windows)]
 mod bt_wheel_blocker;
-mod control;
-mod hid;
+mod config;
+pub mod control;
+#[cfg(target_os = "linux")]
+mod evdev_wheel_blocker;
+pub mod hid;
+#[cfg(target_os = "linux")]
+mod hidraw_tpmiddle;
+#[cfg(windows)]
 mod hook;
-mod input;
-mod tpmiddle;
+pub mod input;
+pub mod output;
+pub mod protocol;
+#[cfg(windows)]
+mod raw_input_tpmiddle;
+pub mod registry;
+pub mod restore;
+pub mod simulate;
+pub mod tpmiddle;
+mod transport_agnostic;
+#[cfg(windows)]
 mod transport_agnostic_tpmiddle;
+#[cfg(target_os = "linux")]
+mod udev;
+#[cfg(target_os = "linux")]
+mod udev_tpmiddle;
+#[cfg(windows)]
 mod window;
 
-pub use args::Args;
-pub use hid::{DEVICE_INFOS_NOTIFY, DEVICE_INFOS_SINK};
+pub use args::{Args, SubCommand};
+#[cfg(windows)]
 pub use transport_agnostic_tpmiddle::TransportAgnosticTPMiddle;
+#[cfg(target_os = "linux")]
+pub use udev_tpmiddle::UdevTPMiddle;
+#[cfg(windows)]
 pub use window::{hide_console, Devices, Window};
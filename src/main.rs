@@ -3,21 +3,21 @@
 extern crate slog;
 
 use std::borrow::Borrow;
-use std::fs::OpenOptions;
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::path::Path;
 
 use anyhow::*;
 use clap::Clap;
 use log::*;
 use slog::{Drain, Duplicate, Logger, Never};
 use slog_scope::GlobalLoggerGuard;
-use winapi::shared::minwindef::WPARAM;
-use winapi::um::processthreadsapi::{GetCurrentProcess, SetPriorityClass};
-use winapi::um::winbase::HIGH_PRIORITY_CLASS;
 
-use tpmiddle_rs::{
-    c_try, hide_console, Args, Devices, TransportAgnosticTPMiddle, Window, DEVICE_INFOS_NOTIFY,
-    DEVICE_INFOS_SINK,
-};
+use tpmiddle_rs::control::ScrollConfig;
+use tpmiddle_rs::registry::Registry;
+use tpmiddle_rs::restore;
+use tpmiddle_rs::simulate;
+use tpmiddle_rs::{Args, SubCommand};
 
 fn set_logger(log: Option<&str>) -> Result<GlobalLoggerGuard> {
     let file_drain: Box<dyn slog::Drain<Ok = (), Err = Never> + Send> = if let Some(log) = log {
@@ -55,15 +55,66 @@ fn set_logger(log: Option<&str>) -> Result<GlobalLoggerGuard> {
     Ok(guard)
 }
 
-fn try_main(args: Args) -> Result<WPARAM> {
+#[cfg(windows)]
+fn try_main(args: Args, registry: &'static Registry) -> Result<i32> {
+    use winapi::um::processthreadsapi::{GetCurrentProcess, SetPriorityClass};
+    use winapi::um::winbase::HIGH_PRIORITY_CLASS;
+
+    use tpmiddle_rs::{c_try, hide_console, Devices, TransportAgnosticTPMiddle, Window};
+
     c_try!(SetPriorityClass(GetCurrentProcess(), HIGH_PRIORITY_CLASS))?;
 
-    let app = TransportAgnosticTPMiddle::new(args, DEVICE_INFOS_NOTIFY);
+    let scroll = args.scroll_config()?;
+    let app = TransportAgnosticTPMiddle::new(args, scroll, registry);
     let window = Window::new("MainWindow", app)?;
-    let _devices = Devices::new(&window, &DEVICE_INFOS_NOTIFY, &DEVICE_INFOS_SINK)?;
+    let _devices = Devices::new(
+        &window,
+        &registry.notify_device_infos(),
+        &registry.sink_device_infos(),
+    )?;
 
     hide_console();
-    window.run()
+    Ok(window.run()? as i32)
+}
+
+#[cfg(target_os = "linux")]
+fn try_main(args: Args, registry: &'static Registry) -> Result<i32> {
+    use tpmiddle_rs::UdevTPMiddle;
+
+    let scroll = args.scroll_config()?;
+    let mut app = UdevTPMiddle::new(args, scroll, registry)?;
+    app.run()?;
+    Ok(0)
+}
+
+#[cfg(not(any(windows, target_os = "linux")))]
+fn try_main(_args: Args, _registry: &'static Registry) -> Result<i32> {
+    bail!("This platform is not supported yet")
+}
+
+fn simulate(scroll: &ScrollConfig, trace: &str, output: Option<&str>) -> Result<i32> {
+    let trace = simulate::load_trace(Path::new(trace))?;
+    let outputs = simulate::simulate(scroll, trace);
+    match output {
+        Some(path) => {
+            let file = File::create(path).with_context(|| format!("creating {:?}", path))?;
+            simulate::write_csv(&outputs, io::BufWriter::new(file))?
+        }
+        None => {
+            let stdout = io::stdout();
+            simulate::write_csv(&outputs, stdout.lock())?
+        }
+    }
+    Ok(0)
+}
+
+fn load_registry(devices: Option<&str>) -> Result<&'static Registry> {
+    let registry = match devices {
+        Some(path) => Registry::load(Path::new(path))?,
+        None => Registry::builtin(),
+    };
+    // Connections refer to the models until the program exits.
+    Ok(Box::leak(Box::new(registry)))
 }
 
 fn main() {
@@ -76,14 +127,40 @@ fn main() {
         eprintln!("Argument error: Flag 'fn-lock' and 'no-fn-lock' cannot be used simultaneously",);
         std::process::exit(-1);
     }
+    if cfg!(windows) && args.sticky {
+        eprintln!("Argument error: --sticky is not supported on Windows");
+        std::process::exit(-1);
+    }
 
     let _logger =
         set_logger(args.log.as_ref().map(Borrow::borrow)).expect("Error: Cannot install logger");
 
-    std::panic::set_hook(Box::new(|info| error!("Error: {:?}", info)));
-
-    match try_main(args) {
-        Ok(code) => std::process::exit(code as i32),
+    std::panic::set_hook(Box::new(|info| {
+        error!("Error: {:?}", info);
+        restore::restore_keyboards();
+    }));
+
+    let result = load_registry(args.devices.as_ref().map(Borrow::borrow)).and_then(|registry| {
+        match args.command {
+            Some(SubCommand::Simulate {
+                ref trace,
+                ref output,
+            }) => simulate(
+                &args.scroll_config()?,
+                trace,
+                output.as_ref().map(Borrow::borrow),
+            ),
+            None => {
+                if let Err(err) = restore::install_signal_handler() {
+                    warn!("Cannot install the signal handler: {:?}", err);
+                }
+                try_main(args, registry)
+            }
+        }
+    });
+    restore::restore_keyboards();
+    match result {
+        Ok(code) => std::process::exit(code),
         Err(err) => {
             error!("Error: {:?}", err);
             std::process::exit(-1);
@@ -0,0 +1,199 @@
+//! Where the scroll controllers and the middle button actions emit mouse events.
+
+use std::sync::Mutex;
+
+use crate::control::ScrollControl;
+use crate::input::{Axis, Button, WHEEL_DELTA};
+use crate::tpmiddle::Action;
+
+/// Emits mouse events to the OS, or anywhere else.
+pub trait OutputSink: Send + Sync {
+    /// Emits a wheel event of `mouse_data` units along the axis.
+    /// A notch is [`WHEEL_DELTA`](crate::input::WHEEL_DELTA) units.
+    fn send_wheel(&self, axis: Axis, mouse_data: i32);
+    fn send_click(&self, button: Button);
+    /// Presses or releases the button, for a drag.
+    fn send_button(&self, button: Button, pressed: bool);
+    /// Moves the pointer by the pixels, right and down.
+    fn send_motion(&self, dx: i32, dy: i32);
+}
+
+/// Delivers the actions of [`TPMiddle`](crate::tpmiddle::TPMiddle) to the scroll controller and
+/// the sink.
+pub fn perform(actions: Vec<Action>, control: &dyn ScrollControl, output: &dyn OutputSink) {
+    for action in actions {
+        match action {
+            Action::Click(button) => output.send_click(button),
+            Action::Press => control.press(),
+            Action::Scroll(delta) => control.scroll(delta),
+            Action::StopScroll => control.stop(),
+            Action::HaltScroll => control.halt(),
+            Action::ButtonDown(button) => output.send_button(button, true),
+            Action::ButtonUp(button) => output.send_button(button, false),
+            Action::Motion { dx, dy } => output.send_motion(dx, dy),
+            Action::Zoom(steps) => output.send_wheel(Axis::Vertical, steps * WHEEL_DELTA),
+        }
+    }
+}
+
+#[derive(Eq, PartialEq, Copy, Clone, Debug)]
+pub enum OutputEvent {
+    Wheel { axis: Axis, mouse_data: i32 },
+    Click(Button),
+    Button { button: Button, pressed: bool },
+    Motion { dx: i32, dy: i32 },
+}
+
+/// Records the events instead of emitting them.
+#[derive(Default)]
+pub struct RecordingSink {
+    events: Mutex<Vec<OutputEvent>>,
+}
+
+impl RecordingSink {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Takes the events recorded so far.
+    pub fn take(&self) -> Vec<OutputEvent> {
+        std::mem::take(&mut *self.events.lock().unwrap())
+    }
+}
+
+impl OutputSink for RecordingSink {
+    fn send_wheel(&self, axis: Axis, mouse_data: i32) {
+        let mut events = self.events.lock().unwrap();
+        events.push(OutputEvent::Wheel { axis, mouse_data });
+    }
+
+    fn send_click(&self, button: Button) {
+        let mut events = self.events.lock().unwrap();
+        events.push(OutputEvent::Click(button));
+    }
+
+    fn send_button(&self, button: Button, pressed: bool) {
+        let mut events = self.events.lock().unwrap();
+        events.push(OutputEvent::Button { button, pressed });
+    }
+
+    fn send_motion(&self, dx: i32, dy: i32) {
+        let mut events = self.events.lock().unwrap();
+        events.push(OutputEvent::Motion { dx, dy });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync::Arc;
+    use std::time::{Duration, Instant};
+
+    use crate::control::{AccelController, AccelParams, ScrollConfig, ScrollControlType};
+    use crate::input::{Event, Modifier, Modifiers, ScrollDelta};
+    use crate::tpmiddle::{
+        ModifierRules, PassthroughParams, PassthroughTrigger, TPMiddle, TPMiddleParams,
+    };
+
+    fn vertical(dy: i8) -> ScrollDelta {
+        ScrollDelta { dx: 0, dy }
+    }
+
+    fn wheel(axis: Axis, mouse_data: i32) -> OutputEvent {
+        OutputEvent::Wheel { axis, mouse_data }
+    }
+
+    fn classic_control(sink: &Arc<RecordingSink>) -> Box<dyn ScrollControl> {
+        ScrollConfig::from_sections(ScrollControlType::Classic, None, &[])
+            .unwrap()
+            .create_control(sink.clone())
+    }
+
+    #[test]
+    fn classic_controller_emits_notches() {
+        let sink = Arc::new(RecordingSink::new());
+        let control = classic_control(&sink);
+        control.scroll(vertical(2));
+        control.scroll(ScrollDelta { dx: -1, dy: 1 });
+        assert_eq!(
+            sink.take(),
+            vec![
+                wheel(Axis::Vertical, 2 * WHEEL_DELTA),
+                wheel(Axis::Vertical, WHEEL_DELTA),
+                wheel(Axis::Horizontal, -WHEEL_DELTA),
+            ]
+        );
+        assert!(sink.take().is_empty());
+    }
+
+    #[test]
+    fn accel_controller_emits_right_away() {
+        let sink = Arc::new(RecordingSink::new());
+        let control = AccelController::new(AccelParams::default(), sink.clone());
+        // The first scroll of each direction counts as the max feed interval, so the distance
+        // doesn't depend on the time.
+        control.scroll(vertical(1));
+        control.scroll(vertical(-1));
+        assert_eq!(
+            sink.take(),
+            vec![wheel(Axis::Vertical, 48), wheel(Axis::Vertical, -49)]
+        );
+    }
+
+    #[test]
+    fn tpmiddle_actions() {
+        let sink = Arc::new(RecordingSink::new());
+        let control = classic_control(&sink);
+        let mut tpmiddle = TPMiddle::with_params(TPMiddleParams {
+            passthrough: Some(PassthroughParams {
+                trigger: PassthroughTrigger::Modifier(Modifier::Shift),
+                ..PassthroughParams::default()
+            }),
+            modifier_rules: Some(ModifierRules::default()),
+            ..TPMiddleParams::default()
+        });
+        let origin = Instant::now();
+        let none = Modifiers::default();
+        let held = |modifier| {
+            let mut modifiers = Modifiers::default();
+            modifiers.insert(modifier);
+            modifiers
+        };
+        let events = vec![
+            // Middle drag
+            (0, Event::ButtonDown, held(Modifier::Shift)),
+            (10, Event::Scroll(ScrollDelta { dx: 1, dy: 2 }), none),
+            (20, Event::ButtonUp, none),
+            // Middle click
+            (100, Event::ButtonDown, none),
+            (110, Event::ButtonUp, none),
+            // Scroll, and zoom by 3 units
+            (200, Event::ButtonDown, none),
+            (210, Event::Scroll(vertical(-1)), none),
+            (220, Event::Scroll(vertical(3)), held(Modifier::Ctrl)),
+            (230, Event::ButtonUp, none),
+        ];
+        for (time_ms, event, modifiers) in events {
+            let time = origin + Duration::from_millis(time_ms);
+            perform(tpmiddle.handle(time, event, modifiers), &*control, &*sink);
+        }
+        assert_eq!(
+            sink.take(),
+            vec![
+                OutputEvent::Button {
+                    button: Button::Middle,
+                    pressed: true
+                },
+                OutputEvent::Motion { dx: 4, dy: -8 },
+                OutputEvent::Button {
+                    button: Button::Middle,
+                    pressed: false
+                },
+                OutputEvent::Click(Button::Middle),
+                wheel(Axis::Vertical, -WHEEL_DELTA),
+                wheel(Axis::Vertical, WHEEL_DELTA),
+            ]
+        );
+    }
+}
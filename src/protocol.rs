@@ -0,0 +1,414 @@
+//! HID reports of ThinkPad TrackPoint keyboards.
+//!
+//! The report IDs and command codes vary by model, so they are given by [`InputReportIds`] and
+//! [`CommandSet`]. Their defaults are the ones of ThinkPad TrackPoint Keyboard II.
+
+use thiserror::*;
+
+pub const REPORT_ID_MIDDLE_BUTTON: u8 = 0x15;
+pub const REPORT_ID_SCROLL: u8 = 0x16;
+/// Carries the same payload as `REPORT_ID_SCROLL`.
+pub const REPORT_ID_SCROLL_ALT: u8 = 0x22;
+
+/// Feature report ID of the commands over USB.
+pub const REPORT_ID_COMMAND_USB: u8 = 0x13;
+/// Output report ID of the commands over Bluetooth.
+pub const REPORT_ID_COMMAND_BT: u8 = 0x18;
+
+const COMMAND_REPORT_LEN_USB: usize = 8;
+const COMMAND_REPORT_LEN_BT: usize = 3;
+
+const MIDDLE_BUTTON_PRESSED: u8 = 0x04;
+
+const COMMAND_SENSITIVITY: u8 = 0x02;
+const COMMAND_FN_LOCK: u8 = 0x05;
+const COMMAND_NATIVE_MIDDLE_BUTTON: u8 = 0x09;
+
+pub const MIN_SENSITIVITY: u8 = 1;
+pub const MAX_SENSITIVITY: u8 = 9;
+
+#[derive(Error, Debug, Eq, PartialEq)]
+pub enum ProtocolError {
+    #[error("Report {report_id:#x} is too short: expected {expected} bytes, got {actual} bytes")]
+    TooShort {
+        report_id: u8,
+        expected: usize,
+        actual: usize,
+    },
+    #[error("Empty report")]
+    Empty,
+    #[error("Unknown report ID: {0:#x}")]
+    UnknownReportId(u8),
+    #[error("Unknown command: {0:#x}")]
+    UnknownCommand(u8),
+    #[error("Invalid value {value:#x} for command {command:#x}")]
+    InvalidValue { command: u8, value: u8 },
+}
+
+fn check_len(packet: &[u8], expected: usize) -> Result<(), ProtocolError> {
+    if packet.len() < expected {
+        return Err(ProtocolError::TooShort {
+            report_id: packet[0],
+            expected,
+            actual: packet.len(),
+        });
+    }
+    Ok(())
+}
+
+/// Report IDs of the vendor input reports.
+#[derive(Eq, PartialEq, Clone, Debug)]
+pub struct InputReportIds {
+    pub middle_button: u8,
+    /// The first one is used to encode.
+    pub scroll: Vec<u8>,
+}
+
+impl Default for InputReportIds {
+    fn default() -> Self {
+        Self {
+            middle_button: REPORT_ID_MIDDLE_BUTTON,
+            scroll: vec![REPORT_ID_SCROLL, REPORT_ID_SCROLL_ALT],
+        }
+    }
+}
+
+/// Vendor input reports. The first byte of an encoded report is the report ID.
+#[derive(Eq, PartialEq, Copy, Clone, Debug)]
+pub enum InputReport {
+    MiddleButton { pressed: bool },
+    Scroll { dx: i8, dy: i8 },
+}
+
+impl InputReport {
+    const LEN: usize = 3;
+
+    pub fn decode(ids: &InputReportIds, packet: &[u8]) -> Result<Self, ProtocolError> {
+        let report_id = *packet.first().ok_or(ProtocolError::Empty)?;
+        if report_id == ids.middle_button {
+            check_len(packet, Self::LEN)?;
+            Ok(InputReport::MiddleButton {
+                pressed: packet[2] & MIDDLE_BUTTON_PRESSED != 0x00,
+            })
+        } else if ids.scroll.contains(&report_id) {
+            check_len(packet, Self::LEN)?;
+            Ok(InputReport::Scroll {
+                dx: packet[1] as i8,
+                dy: packet[2] as i8,
+            })
+        } else {
+            Err(ProtocolError::UnknownReportId(report_id))
+        }
+    }
+
+    pub fn encode(&self, ids: &InputReportIds) -> [u8; Self::LEN] {
+        match *self {
+            InputReport::MiddleButton { pressed } => {
+                let flags = if pressed { MIDDLE_BUTTON_PRESSED } else { 0x00 };
+                [ids.middle_button, 0x00, flags]
+            }
+            InputReport::Scroll { dx, dy } => [ids.scroll[0], dx as u8, dy as u8],
+        }
+    }
+}
+
+/// How the commands are sent to the keyboard.
+#[derive(Eq, PartialEq, Copy, Clone, Debug)]
+pub enum ReportType {
+    /// Set and get with feature reports.
+    Feature,
+    /// Set with output reports, and get with a query output report and its input report response.
+    Output,
+}
+
+/// Framing and codes of the commands.
+#[derive(Eq, PartialEq, Copy, Clone, Debug)]
+pub struct CommandSet {
+    pub report_type: ReportType,
+    pub report_id: u8,
+    pub report_len: usize,
+    pub sensitivity: u8,
+    pub fn_lock: u8,
+    pub native_middle_button: u8,
+}
+
+impl CommandSet {
+    /// 8-byte feature reports of ThinkPad TrackPoint Keyboard II over USB.
+    pub const FEATURE_REPORT: CommandSet = CommandSet {
+        report_type: ReportType::Feature,
+        report_id: REPORT_ID_COMMAND_USB,
+        report_len: COMMAND_REPORT_LEN_USB,
+        sensitivity: COMMAND_SENSITIVITY,
+        fn_lock: COMMAND_FN_LOCK,
+        native_middle_button: COMMAND_NATIVE_MIDDLE_BUTTON,
+    };
+
+    /// 3-byte output reports of ThinkPad TrackPoint Keyboard II over Bluetooth.
+    pub const OUTPUT_REPORT: CommandSet = CommandSet {
+        report_type: ReportType::Output,
+        report_id: REPORT_ID_COMMAND_BT,
+        report_len: COMMAND_REPORT_LEN_BT,
+        sensitivity: COMMAND_SENSITIVITY,
+        fn_lock: COMMAND_FN_LOCK,
+        native_middle_button: COMMAND_NATIVE_MIDDLE_BUTTON,
+    };
+
+    fn code(&self, setting: Setting) -> u8 {
+        match setting {
+            Setting::Sensitivity => self.sensitivity,
+            Setting::FnLock => self.fn_lock,
+            Setting::NativeMiddleButton => self.native_middle_button,
+        }
+    }
+
+    fn setting(&self, code: u8) -> Option<Setting> {
+        Setting::ALL
+            .iter()
+            .copied()
+            .find(|&setting| self.code(setting) == code)
+    }
+
+    fn packet(&self, code: u8, value: u8) -> Vec<u8> {
+        // Commands have at least a report ID, a code and a value.
+        let mut packet = vec![0x00; self.report_len.max(3)];
+        packet[0] = self.report_id;
+        packet[1] = code;
+        packet[2] = value;
+        packet
+    }
+}
+
+/// Configuration commands.
+#[derive(Eq, PartialEq, Copy, Clone, Debug)]
+pub enum Command {
+    /// TrackPoint sensitivity in `[MIN_SENSITIVITY, MAX_SENSITIVITY]`.
+    Sensitivity(u8),
+    FnLock(bool),
+    /// When it is disabled, the keyboard sends middle button and scroll reports instead of
+    /// "ThinkPad preferred scroll".
+    NativeMiddleButton(bool),
+}
+
+impl Command {
+    pub fn decode(set: &CommandSet, packet: &[u8]) -> Result<Self, ProtocolError> {
+        let report_id = *packet.first().ok_or(ProtocolError::Empty)?;
+        if report_id != set.report_id {
+            return Err(ProtocolError::UnknownReportId(report_id));
+        }
+        check_len(packet, set.report_len.max(3))?;
+        let (code, value) = (packet[1], packet[2]);
+        let setting = set
+            .setting(code)
+            .ok_or(ProtocolError::UnknownCommand(code))?;
+        Self::from_value(setting, value).ok_or(ProtocolError::InvalidValue {
+            command: code,
+            value,
+        })
+    }
+
+    pub fn encode(&self, set: &CommandSet) -> Result<Vec<u8>, ProtocolError> {
+        let code = set.code(self.setting());
+        let value = self.value();
+        if Self::from_value(self.setting(), value).is_none() {
+            return Err(ProtocolError::InvalidValue {
+                command: code,
+                value,
+            });
+        }
+        Ok(set.packet(code, value))
+    }
+
+    pub fn setting(&self) -> Setting {
+        match self {
+            Command::Sensitivity(_) => Setting::Sensitivity,
+            Command::FnLock(_) => Setting::FnLock,
+            Command::NativeMiddleButton(_) => Setting::NativeMiddleButton,
+        }
+    }
+
+    fn from_value(setting: Setting, value: u8) -> Option<Self> {
+        match setting {
+            Setting::Sensitivity if (MIN_SENSITIVITY..=MAX_SENSITIVITY).contains(&value) => {
+                Some(Command::Sensitivity(value))
+            }
+            Setting::Sensitivity => None,
+            Setting::FnLock => match value {
+                0x00 => Some(Command::FnLock(false)),
+                0x01 => Some(Command::FnLock(true)),
+                _ => None,
+            },
+            Setting::NativeMiddleButton => match value {
+                // 0x00: Keyboard sends scroll events
+                // 0x01: "ThinkPad preferred scroll".
+                0x00 => Some(Command::NativeMiddleButton(true)),
+                0x01 => Some(Command::NativeMiddleButton(false)),
+                _ => None,
+            },
+        }
+    }
+
+    fn value(self) -> u8 {
+        match self {
+            Command::Sensitivity(sensitivity) => sensitivity,
+            Command::FnLock(enable) => {
+                if enable {
+                    0x01
+                } else {
+                    0x00
+                }
+            }
+            Command::NativeMiddleButton(enable) => {
+                if enable {
+                    0x00
+                } else {
+                    0x01
+                }
+            }
+        }
+    }
+}
+
+/// The settings that commands change.
+#[derive(Eq, PartialEq, Copy, Clone, Debug)]
+pub enum Setting {
+    Sensitivity,
+    FnLock,
+    NativeMiddleButton,
+}
+
+impl Setting {
+    pub const ALL: [Setting; 3] = [
+        Setting::Sensitivity,
+        Setting::FnLock,
+        Setting::NativeMiddleButton,
+    ];
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const COMMANDS: [Command; 6] = [
+        Command::Sensitivity(MIN_SENSITIVITY),
+        Command::Sensitivity(MAX_SENSITIVITY),
+        Command::FnLock(false),
+        Command::FnLock(true),
+        Command::NativeMiddleButton(false),
+        Command::NativeMiddleButton(true),
+    ];
+
+    #[test]
+    fn input_report_round_trip() {
+        let ids = InputReportIds::default();
+        for report in &[
+            InputReport::MiddleButton { pressed: false },
+            InputReport::MiddleButton { pressed: true },
+            InputReport::Scroll { dx: -128, dy: 127 },
+            InputReport::Scroll { dx: 1, dy: -1 },
+        ] {
+            assert_eq!(InputReport::decode(&ids, &report.encode(&ids)), Ok(*report));
+        }
+    }
+
+    #[test]
+    fn input_report_alt_scroll_id() {
+        let ids = InputReportIds::default();
+        assert_eq!(
+            InputReport::decode(&ids, &[REPORT_ID_SCROLL_ALT, 0x02, 0xff]),
+            Ok(InputReport::Scroll { dx: 2, dy: -1 })
+        );
+    }
+
+    #[test]
+    fn command_round_trip() {
+        for set in &[CommandSet::FEATURE_REPORT, CommandSet::OUTPUT_REPORT] {
+            for command in &COMMANDS {
+                let packet = command.encode(set).unwrap();
+                assert_eq!(packet.len(), set.report_len);
+                assert_eq!(Command::decode(set, &packet), Ok(*command));
+            }
+        }
+    }
+
+    #[test]
+    fn empty_packet() {
+        let ids = InputReportIds::default();
+        assert_eq!(InputReport::decode(&ids, &[]), Err(ProtocolError::Empty));
+        assert_eq!(
+            Command::decode(&CommandSet::FEATURE_REPORT, &[]),
+            Err(ProtocolError::Empty)
+        );
+    }
+
+    #[test]
+    fn short_packet() {
+        let ids = InputReportIds::default();
+        assert_eq!(
+            InputReport::decode(&ids, &[REPORT_ID_SCROLL, 0x01]),
+            Err(ProtocolError::TooShort {
+                report_id: REPORT_ID_SCROLL,
+                expected: 3,
+                actual: 2,
+            })
+        );
+        assert_eq!(
+            Command::decode(
+                &CommandSet::FEATURE_REPORT,
+                &[REPORT_ID_COMMAND_USB, COMMAND_FN_LOCK, 0x01]
+            ),
+            Err(ProtocolError::TooShort {
+                report_id: REPORT_ID_COMMAND_USB,
+                expected: COMMAND_REPORT_LEN_USB,
+                actual: 3,
+            })
+        );
+    }
+
+    #[test]
+    fn unknown_report_id() {
+        let ids = InputReportIds::default();
+        assert_eq!(
+            InputReport::decode(&ids, &[0x42, 0x00, 0x00]),
+            Err(ProtocolError::UnknownReportId(0x42))
+        );
+        assert_eq!(
+            Command::decode(&CommandSet::OUTPUT_REPORT, &[0x42, COMMAND_FN_LOCK, 0x01]),
+            Err(ProtocolError::UnknownReportId(0x42))
+        );
+        assert_eq!(
+            Command::decode(
+                &CommandSet::OUTPUT_REPORT,
+                &[REPORT_ID_COMMAND_BT, 0x7f, 0x01]
+            ),
+            Err(ProtocolError::UnknownCommand(0x7f))
+        );
+    }
+
+    #[test]
+    fn out_of_range_values() {
+        let set = CommandSet::OUTPUT_REPORT;
+        for &sensitivity in &[MIN_SENSITIVITY - 1, MAX_SENSITIVITY + 1] {
+            assert_eq!(
+                Command::Sensitivity(sensitivity).encode(&set),
+                Err(ProtocolError::InvalidValue {
+                    command: COMMAND_SENSITIVITY,
+                    value: sensitivity,
+                })
+            );
+        }
+        assert_eq!(
+            Command::decode(&set, &[REPORT_ID_COMMAND_BT, COMMAND_SENSITIVITY, 10]),
+            Err(ProtocolError::InvalidValue {
+                command: COMMAND_SENSITIVITY,
+                value: 10,
+            })
+        );
+        assert_eq!(
+            Command::decode(&set, &[REPORT_ID_COMMAND_BT, COMMAND_FN_LOCK, 0x02]),
+            Err(ProtocolError::InvalidValue {
+                command: COMMAND_FN_LOCK,
+                value: 0x02,
+            })
+        );
+    }
+}
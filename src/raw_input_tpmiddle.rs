@@ -0,0 +1,106 @@
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use anyhow::*;
+use winapi::shared::basetsd::UINT_PTR;
+use winapi::shared::minwindef::{LPARAM, UINT, WPARAM};
+use winapi::shared::windef::HWND;
+use winapi::um::winuser::{KillTimer, SetTimer, HRAWINPUT, WM_INPUT, WM_TIMER};
+
+use crate::control::ScrollControl;
+use crate::input::{get_modifiers, EventReader};
+use crate::output::{self, OutputSink};
+use crate::registry::DeviceModel;
+use crate::tpmiddle::{Action, TPMiddle};
+use crate::window::{WindowProc, WindowProcError, WindowProcResult};
+
+/// The timer of [`TPMiddle::deadline`].
+const TIMEOUT_TIMER_ID: UINT_PTR = 1;
+
+/// Feeds `WM_INPUT` messages into [`TPMiddle`] and delivers its actions to the output.
+pub struct RawInputTPMiddle {
+    tpmiddle: TPMiddle,
+    control: Box<dyn ScrollControl>,
+    event_reader: EventReader<'static>,
+    output: Arc<dyn OutputSink>,
+}
+
+impl RawInputTPMiddle {
+    pub fn new(
+        model: &'static DeviceModel,
+        tpmiddle: TPMiddle,
+        control: Box<dyn ScrollControl>,
+        output: Arc<dyn OutputSink>,
+    ) -> Self {
+        RawInputTPMiddle {
+            tpmiddle,
+            control,
+            event_reader: EventReader::new(model),
+            output,
+        }
+    }
+
+    fn perform(&self, actions: Vec<Action>) {
+        output::perform(actions, &*self.control, &*self.output);
+    }
+
+    /// Sets the timer to the deadline of [`TPMiddle`], or kills it if there is none.
+    fn schedule_timeout(&self, hwnd: HWND) -> Result<()> {
+        match self.tpmiddle.deadline() {
+            Some(deadline) => {
+                // Round up not to wake up right before the deadline.
+                let delay =
+                    deadline.saturating_duration_since(Instant::now()) + Duration::from_millis(1);
+                // It replaces the timer of the same ID.
+                c_try_nonnull!(SetTimer(
+                    hwnd,
+                    TIMEOUT_TIMER_ID,
+                    delay.as_millis() as UINT,
+                    None
+                ))?;
+            }
+            None => unsafe {
+                // Fails if it is not set.
+                KillTimer(hwnd, TIMEOUT_TIMER_ID);
+            },
+        }
+        Ok(())
+    }
+}
+
+impl WindowProc for RawInputTPMiddle {
+    fn proc(
+        &mut self,
+        hwnd: HWND,
+        u_msg: UINT,
+        w_param: WPARAM,
+        l_param: LPARAM,
+    ) -> WindowProcResult {
+        match u_msg {
+            WM_INPUT => {
+                let events = if let Ok(events) =
+                    self.event_reader.read_from_raw_input(l_param as HRAWINPUT)
+                {
+                    events
+                } else {
+                    return Ok(0);
+                };
+
+                let now = Instant::now();
+                let modifiers = get_modifiers();
+                for event in events {
+                    let actions = self.tpmiddle.handle(now, event, modifiers);
+                    self.perform(actions);
+                }
+            }
+            WM_TIMER if w_param == TIMEOUT_TIMER_ID => {
+                let actions = self.tpmiddle.handle_timeout(Instant::now());
+                self.perform(actions);
+            }
+            _ => return Err(WindowProcError::UnhandledMessage),
+        }
+
+        self.schedule_timeout(hwnd)?;
+        Ok(0)
+    }
+}
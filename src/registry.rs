@@ -0,0 +1,466 @@
+//! Device models that tpmiddle-rs supports.
+//!
+//! ThinkPad TrackPoint Keyboard II is built in. Other models are added from a file of `[model]`
+//! sections. For example, a hypothetical keyboard that speaks the protocol of the Keyboard II:
+//!
+//! ```text
+//! [model]
+//! name = Hypothetical TrackPoint Keyboard
+//! vendor_id = 0x17EF
+//! product_id = 0x1234
+//! transport = usb
+//! # `usage_page:usage` of the top-level collections in the report descriptor of the keyboard
+//! command_collection = 0x0C:0x01
+//! middle_button_collection = 0xFFA0:0x01
+//! # Optional
+//! wheel_collection = 0xFF10:0x01
+//! native_wheel_collection = 0x01:0x02
+//! # Optional. The defaults are the ones of ThinkPad TrackPoint Keyboard II over the transport.
+//! command_report_type = feature
+//! command_report_id = 0x13
+//! command_report_len = 8
+//! sensitivity_command = 0x02
+//! fn_lock_command = 0x05
+//! native_middle_button_command = 0x09
+//! middle_button_report_id = 0x15
+//! scroll_report_ids = 0x16, 0x22
+//! ```
+//!
+//! A model with the same vendor and product IDs as a built-in one replaces it. The models in a file
+//! must have distinct IDs.
+
+use std::path::Path;
+
+use anyhow::*;
+
+use crate::config::{self, parse_int, parse_list, Section};
+use crate::hid::{DeviceInfo, Transport, PID_BT, PID_USB, VID_LENOVO};
+use crate::protocol::{CommandSet, InputReportIds, ReportType};
+
+/// A top-level collection of the HID interface.
+#[derive(Eq, PartialEq, Copy, Clone, Debug)]
+pub struct Collection {
+    pub usage_page: u16,
+    pub usage: u16,
+}
+
+impl Collection {
+    fn parse(value: &str) -> Result<Self> {
+        let mut parts = value.splitn(2, ':');
+        let usage_page = parse_int(parts.next().unwrap().trim())?;
+        let usage = parse_int(
+            parts
+                .next()
+                .ok_or_else(|| anyhow!("expected `usage_page:usage`"))?
+                .trim(),
+        )?;
+        Ok(Self { usage_page, usage })
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct DeviceModel {
+    pub name: String,
+    pub vendor_id: u16,
+    pub product_id: u16,
+    pub transport: Transport,
+    /// Receives the commands.
+    pub command_collection: Collection,
+    /// Sends the middle button reports.
+    pub middle_button_collection: Collection,
+    /// Sends the scroll reports, if it is another collection.
+    pub wheel_collection: Option<Collection>,
+    /// Sends the native wheel events that are blocked while tpmiddle-rs scrolls.
+    pub native_wheel_collection: Option<Collection>,
+    pub command_set: CommandSet,
+    pub input_report_ids: InputReportIds,
+}
+
+impl DeviceModel {
+    pub fn device_info(&self, collection: Collection) -> DeviceInfo {
+        DeviceInfo {
+            vendor_id: self.vendor_id,
+            product_id: self.product_id,
+            usage_page: collection.usage_page,
+            usage: collection.usage,
+        }
+    }
+
+    pub fn is_model_of(&self, device_info: &DeviceInfo) -> bool {
+        self.vendor_id == device_info.vendor_id && self.product_id == device_info.product_id
+    }
+
+    /// The collections that send the vendor input reports.
+    pub fn input_device_infos(&self) -> Vec<DeviceInfo> {
+        Some(self.middle_button_collection)
+            .into_iter()
+            .chain(self.wheel_collection)
+            .map(|collection| self.device_info(collection))
+            .collect()
+    }
+
+    /// The collections whose arrival and removal are notified.
+    fn notify_device_infos(&self) -> impl Iterator<Item = DeviceInfo> + '_ {
+        Some(self.middle_button_collection)
+            .into_iter()
+            .chain(self.native_wheel_collection)
+            .map(move |collection| self.device_info(collection))
+    }
+
+    /// The collections whose inputs are received in background.
+    fn sink_device_infos(&self) -> impl Iterator<Item = DeviceInfo> + '_ {
+        Some(self.middle_button_collection)
+            .into_iter()
+            .chain(self.wheel_collection)
+            .chain(self.native_wheel_collection)
+            .map(move |collection| self.device_info(collection))
+    }
+
+    fn from_section(section: &Section) -> Result<Self> {
+        section.check_keys(&[
+            "name",
+            "vendor_id",
+            "product_id",
+            "transport",
+            "command_collection",
+            "middle_button_collection",
+            "wheel_collection",
+            "native_wheel_collection",
+            "command_report_type",
+            "command_report_id",
+            "command_report_len",
+            "sensitivity_command",
+            "fn_lock_command",
+            "native_middle_button_command",
+            "middle_button_report_id",
+            "scroll_report_ids",
+        ])?;
+
+        let transport: Transport = section.require("transport", str::parse)?;
+        let default_command_set = match transport {
+            Transport::USB => CommandSet::FEATURE_REPORT,
+            Transport::BT => CommandSet::OUTPUT_REPORT,
+        };
+        let default_report_ids = InputReportIds::default();
+        let report_type = section.get("command_report_type", |value| match value {
+            "feature" => Ok(ReportType::Feature),
+            "output" => Ok(ReportType::Output),
+            _ => bail!("`{}` is not `feature` or `output`", value),
+        })?;
+        let scroll_report_ids = section.get("scroll_report_ids", |value| {
+            let ids = parse_list(value, parse_int)?;
+            if ids.is_empty() {
+                bail!("requires at least one report ID");
+            }
+            Ok(ids)
+        })?;
+
+        Ok(Self {
+            name: section.require("name", |value| Ok(value.to_owned()))?,
+            vendor_id: section.require("vendor_id", parse_int)?,
+            product_id: section.require("product_id", parse_int)?,
+            transport,
+            command_collection: section.require("command_collection", Collection::parse)?,
+            middle_button_collection: section
+                .require("middle_button_collection", Collection::parse)?,
+            wheel_collection: section.get("wheel_collection", Collection::parse)?,
+            native_wheel_collection: section.get("native_wheel_collection", Collection::parse)?,
+            command_set: CommandSet {
+                report_type: report_type.unwrap_or(default_command_set.report_type),
+                report_id: section
+                    .get("command_report_id", parse_int)?
+                    .unwrap_or(default_command_set.report_id),
+                report_len: section
+                    .get("command_report_len", parse_int)?
+                    .unwrap_or(default_command_set.report_len),
+                sensitivity: section
+                    .get("sensitivity_command", parse_int)?
+                    .unwrap_or(default_command_set.sensitivity),
+                fn_lock: section
+                    .get("fn_lock_command", parse_int)?
+                    .unwrap_or(default_command_set.fn_lock),
+                native_middle_button: section
+                    .get("native_middle_button_command", parse_int)?
+                    .unwrap_or(default_command_set.native_middle_button),
+            },
+            input_report_ids: InputReportIds {
+                middle_button: section
+                    .get("middle_button_report_id", parse_int)?
+                    .unwrap_or(default_report_ids.middle_button),
+                scroll: scroll_report_ids.unwrap_or(default_report_ids.scroll),
+            },
+        })
+    }
+}
+
+pub struct Registry {
+    models: Vec<DeviceModel>,
+}
+
+impl Registry {
+    pub fn builtin() -> Self {
+        Self {
+            models: vec![
+                DeviceModel {
+                    name: "ThinkPad TrackPoint Keyboard II".to_owned(),
+                    vendor_id: VID_LENOVO,
+                    product_id: PID_USB,
+                    transport: Transport::USB,
+                    command_collection: Collection {
+                        usage_page: 0x0C,
+                        usage: 0x01,
+                    },
+                    middle_button_collection: Collection {
+                        usage_page: 0xFFA0,
+                        usage: 0x01,
+                    },
+                    wheel_collection: Some(Collection {
+                        usage_page: 0xFF10,
+                        usage: 0x01,
+                    }),
+                    native_wheel_collection: None,
+                    command_set: CommandSet::FEATURE_REPORT,
+                    input_report_ids: InputReportIds::default(),
+                },
+                DeviceModel {
+                    name: "ThinkPad TrackPoint Keyboard II".to_owned(),
+                    vendor_id: VID_LENOVO,
+                    product_id: PID_BT,
+                    transport: Transport::BT,
+                    command_collection: Collection {
+                        usage_page: 0xFF01,
+                        usage: 0x01,
+                    },
+                    middle_button_collection: Collection {
+                        usage_page: 0xFF00,
+                        usage: 0x01,
+                    },
+                    wheel_collection: Some(Collection {
+                        usage_page: 0xFF10,
+                        usage: 0x01,
+                    }),
+                    native_wheel_collection: Some(Collection {
+                        usage_page: 0x01,
+                        usage: 0x02,
+                    }),
+                    command_set: CommandSet::OUTPUT_REPORT,
+                    input_report_ids: InputReportIds::default(),
+                },
+            ],
+        }
+    }
+
+    /// Adds the models in the file to the built-in ones.
+    pub fn load(path: &Path) -> Result<Self> {
+        Self::from_sections(&config::load(path)?).with_context(|| format!("reading {:?}", path))
+    }
+
+    fn from_sections(sections: &[Section]) -> Result<Self> {
+        let mut models: Vec<(usize, DeviceModel)> = Vec::new();
+        for section in sections {
+            if section.name != "model" {
+                bail!(
+                    "line {}: unknown section `[{}]`",
+                    section.line,
+                    section.name
+                );
+            }
+            let model = DeviceModel::from_section(section)?;
+            if let Some((line, _)) = models.iter().find(|(_, other)| {
+                other.vendor_id == model.vendor_id && other.product_id == model.product_id
+            }) {
+                bail!(
+                    "line {}: the IDs are the same as the model at line {}",
+                    section.line,
+                    line
+                );
+            }
+            models.push((section.line, model));
+        }
+
+        let mut registry = Self::builtin();
+        for (_, model) in models {
+            registry.add(model);
+        }
+        Ok(registry)
+    }
+
+    pub fn add(&mut self, model: DeviceModel) {
+        self.models.retain(|existing| {
+            existing.vendor_id != model.vendor_id || existing.product_id != model.product_id
+        });
+        self.models.push(model);
+    }
+
+    pub fn models(&self) -> &[DeviceModel] {
+        &self.models
+    }
+
+    pub fn model_of(&self, device_info: &DeviceInfo) -> Option<&DeviceModel> {
+        self.models
+            .iter()
+            .find(|model| model.is_model_of(device_info))
+    }
+
+    pub fn notify_device_infos(&self) -> Vec<DeviceInfo> {
+        self.models
+            .iter()
+            .flat_map(DeviceModel::notify_device_infos)
+            .collect()
+    }
+
+    pub fn sink_device_infos(&self) -> Vec<DeviceInfo> {
+        self.models
+            .iter()
+            .flat_map(DeviceModel::sink_device_infos)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MODEL: &str = "
+        [model]
+        name = Hypothetical TrackPoint Keyboard
+        vendor_id = 0x17EF
+        product_id = 0x1234
+        transport = usb
+        command_collection = 0x0C:0x01
+        middle_button_collection = 0xFFA0:0x01
+        ";
+
+    fn from_text(text: &str) -> Result<Registry> {
+        Registry::from_sections(&config::parse(text)?)
+    }
+
+    fn error_of(text: &str) -> String {
+        format!("{:#}", from_text(text).err().unwrap())
+    }
+
+    #[test]
+    fn defaults_follow_transport() {
+        let registry = from_text(MODEL).unwrap();
+        assert_eq!(registry.models().len(), 3);
+        let model = registry.models().last().unwrap();
+        assert_eq!(model.name, "Hypothetical TrackPoint Keyboard");
+        assert_eq!((model.vendor_id, model.product_id), (VID_LENOVO, 0x1234));
+        assert_eq!(model.transport, Transport::USB);
+        assert_eq!(
+            model.middle_button_collection,
+            Collection {
+                usage_page: 0xFFA0,
+                usage: 0x01
+            }
+        );
+        assert_eq!(model.wheel_collection, None);
+        assert_eq!(model.command_set, CommandSet::FEATURE_REPORT);
+        assert_eq!(model.input_report_ids, InputReportIds::default());
+    }
+
+    #[test]
+    fn overrides() {
+        let text = format!(
+            "{}
+            wheel_collection = 0xFF10:0x01
+            command_report_type = output
+            command_report_id = 0x18
+            command_report_len = 3
+            sensitivity_command = 0x12
+            scroll_report_ids = 0x16, 0x22,
+            ",
+            MODEL
+        );
+        let registry = from_text(&text).unwrap();
+        let model = registry.models().last().unwrap();
+        assert_eq!(
+            model.wheel_collection,
+            Some(Collection {
+                usage_page: 0xFF10,
+                usage: 0x01
+            })
+        );
+        assert_eq!(model.command_set.report_type, ReportType::Output);
+        assert_eq!(model.command_set.report_id, 0x18);
+        assert_eq!(model.command_set.report_len, 3);
+        assert_eq!(model.command_set.sensitivity, 0x12);
+        assert_eq!(
+            model.command_set.fn_lock,
+            CommandSet::FEATURE_REPORT.fn_lock
+        );
+        assert_eq!(model.input_report_ids.scroll, [0x16, 0x22]);
+    }
+
+    #[test]
+    fn replaces_builtin_model() {
+        let text = MODEL.replace("0x1234", "0x60EE");
+        let registry = from_text(&text).unwrap();
+        assert_eq!(registry.models().len(), 2);
+        let model = registry.models().last().unwrap();
+        assert_eq!(model.name, "Hypothetical TrackPoint Keyboard");
+        assert_eq!(model.product_id, PID_USB);
+    }
+
+    #[test]
+    fn rejects_duplicate_models() {
+        let text = format!("{}{}", MODEL, MODEL.replace("usb", "bt"));
+        assert_eq!(
+            error_of(&text),
+            "line 10: the IDs are the same as the model at line 2"
+        );
+    }
+
+    #[test]
+    fn rejects_unknown_keys_and_sections() {
+        let text = format!("{}    fnlock_command = 0x05\n", MODEL);
+        assert_eq!(
+            error_of(&text),
+            "line 9: unknown key `fnlock_command` in `[model]`"
+        );
+        assert_eq!(
+            error_of("[modle]\nname = x\n"),
+            "line 1: unknown section `[modle]`"
+        );
+    }
+
+    #[test]
+    fn rejects_malformed_values() {
+        let product_id = |value: &str| error_of(&MODEL.replace("0x1234", value));
+        assert!(product_id("0xG123").starts_with("line 5: invalid `product_id`: "));
+        assert_eq!(
+            product_id("0x12345"),
+            "line 5: invalid `product_id`: 0x12345 is out of range"
+        );
+        assert!(product_id("").starts_with("line 5: invalid `product_id`: "));
+
+        assert_eq!(
+            error_of(&MODEL.replace("0x0C:0x01", "0x0C")),
+            "line 7: invalid `command_collection`: expected `usage_page:usage`"
+        );
+        assert_eq!(
+            error_of(&MODEL.replace("usb", "serial")),
+            "line 6: invalid `transport`: serial is not a valid connection method"
+        );
+        assert_eq!(
+            error_of(&format!("{}    scroll_report_ids = ,\n", MODEL)),
+            "line 9: invalid `scroll_report_ids`: requires at least one report ID"
+        );
+        assert_eq!(
+            error_of(&MODEL.replace("        product_id = 0x1234\n", "")),
+            "line 2: `[model]` requires `product_id`"
+        );
+    }
+
+    #[test]
+    fn load_names_the_file() {
+        let path = std::env::temp_dir().join(format!("tpmiddle-rs-{}.ini", std::process::id()));
+        std::fs::write(&path, MODEL.replace("0x1234", "0x")).unwrap();
+        let message = format!("{:#}", Registry::load(&path).err().unwrap());
+        std::fs::remove_file(&path).unwrap();
+        assert!(message.starts_with(&format!("reading {:?}: line 5: invalid `product_id`", path)));
+
+        let message = format!("{:#}", Registry::load(&path).err().unwrap());
+        assert!(message.starts_with(&format!("reading {:?}", path)));
+    }
+}
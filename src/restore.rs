@@ -0,0 +1,91 @@
+//! Restores the native behaviour of the keyboards when the program exits.
+
+use std::sync::{Mutex, MutexGuard, PoisonError};
+
+use anyhow::*;
+use log::*;
+
+use crate::hid;
+use crate::registry::DeviceModel;
+
+lazy_static! {
+    static ref INITIALIZED: Mutex<Vec<&'static DeviceModel>> = Mutex::new(Vec::new());
+}
+
+fn initialized() -> MutexGuard<'static, Vec<&'static DeviceModel>> {
+    // It is also used in the panic hook.
+    INITIALIZED.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Records the initialized keyboard.
+pub fn register(model: &'static DeviceModel) {
+    let mut initialized = initialized();
+    if !initialized.iter().any(|init| std::ptr::eq(*init, model)) {
+        initialized.push(model);
+    }
+}
+
+/// Restores the registered keyboards. Restored keyboards are unregistered.
+pub fn restore_keyboards() {
+    let initialized = std::mem::take(&mut *initialized());
+    for model in initialized {
+        match hid::restore_keyboard(model) {
+            Ok(()) => info!("Restored {} over {}", model.name, model.transport),
+            Err(err) => warn!("Cannot restore the keyboard: {}", err),
+        }
+    }
+}
+
+/// Restores the keyboards and exits on SIGINT, SIGTERM and SIGHUP.
+///
+/// It should be called before spawning any thread, since they inherit the blocked signal mask.
+#[cfg(target_os = "linux")]
+pub fn install_signal_handler() -> Result<()> {
+    const SIGNALS: &[libc::c_int] = &[libc::SIGINT, libc::SIGTERM, libc::SIGHUP];
+
+    let set = unsafe {
+        let mut set = std::mem::zeroed::<libc::sigset_t>();
+        libc::sigemptyset(&mut set);
+        for &signal in SIGNALS {
+            libc::sigaddset(&mut set, signal);
+        }
+        set
+    };
+    let result = unsafe { libc::pthread_sigmask(libc::SIG_BLOCK, &set, std::ptr::null_mut()) };
+    if result != 0 {
+        return Err(std::io::Error::from_raw_os_error(result)).context("blocking signals");
+    }
+
+    std::thread::spawn(move || {
+        let mut signal = 0;
+        if unsafe { libc::sigwait(&set, &mut signal) } == 0 {
+            info!("Received signal {}", signal);
+            restore_keyboards();
+            std::process::exit(0);
+        }
+    });
+    Ok(())
+}
+
+/// Restores the keyboards on Ctrl+C, Ctrl+Break, and closing the console.
+///
+/// Logoff and shutdown are notified to the window instead, see [`crate::Window`].
+#[cfg(windows)]
+pub fn install_signal_handler() -> Result<()> {
+    use winapi::shared::minwindef::{BOOL, DWORD, FALSE, TRUE};
+    use winapi::um::consoleapi::SetConsoleCtrlHandler;
+
+    unsafe extern "system" fn handler(_ctrl_type: DWORD) -> BOOL {
+        restore_keyboards();
+        // Let the default handler terminate the process.
+        FALSE
+    }
+
+    c_try!(SetConsoleCtrlHandler(Some(handler), TRUE))?;
+    Ok(())
+}
+
+#[cfg(not(any(windows, target_os = "linux")))]
+pub fn install_signal_handler() -> Result<()> {
+    Ok(())
+}
@@ -0,0 +1,485 @@
+//! Runs a recorded input trace through a scroll controller without touching the OS.
+//!
+//! A trace is a CSV of `time_ms,event[,delta]` rows in time order. A `diagonal` event has two
+//! deltas, `dx,dy`, and so does a `motion` event of the pointer in pixels for the sticky scroll.
+//! A `modifiers` row sets the modifier keys held from then on, `none` or like `ctrl+shift`:
+//!
+//! ```text
+//! # time_ms,event,delta
+//! 0,down
+//! 120.5,vertical,1
+//! 135,horizontal,-2
+//! 150,diagonal,1,-1
+//! 800,up
+//! 900,modifiers,ctrl
+//! 1000,motion,3,-12
+//! ```
+//!
+//! The result is a CSV of the wheel events with `time_ms,axis,mouse_data,distance,latency_ms`.
+//! `distance` is the cumulative `mouse_data` along the axis, and `latency_ms` is the time since
+//! the latest scroll input.
+
+use std::fs;
+use std::io::{self, Write};
+use std::path::Path;
+use std::time::Duration;
+
+use anyhow::*;
+
+use crate::control::{Clock, ManualClock, ScrollConfig, StepControl, WheelTick};
+use crate::input::{Axis, Event, Modifiers, ScrollDelta, WHEEL_DELTA};
+use crate::tpmiddle::Action;
+
+pub struct TraceEvent {
+    /// Since the start of the trace.
+    pub time: Duration,
+    pub event: TraceInput,
+}
+
+pub enum TraceInput {
+    Event(Event),
+    /// The modifier keys held from then on.
+    Modifiers(Modifiers),
+}
+
+pub fn parse_trace(text: &str) -> Result<Vec<TraceEvent>> {
+    let mut trace: Vec<TraceEvent> = Vec::new();
+    for (index, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') || line.starts_with("time") {
+            continue;
+        }
+        let event = parse_trace_event(line).with_context(|| format!("line {}", index + 1))?;
+        if trace.last().is_some_and(|last| event.time < last.time) {
+            bail!("line {}: the time goes backwards", index + 1);
+        }
+        trace.push(event);
+    }
+    Ok(trace)
+}
+
+fn parse_trace_event(line: &str) -> Result<TraceEvent> {
+    let fields: Vec<&str> = line.split(',').map(str::trim).collect();
+    let time_ms: f64 = fields[0].parse().context("invalid time")?;
+    if !(time_ms.is_finite() && time_ms >= 0.0) {
+        bail!("the time should be a finite non-negative number");
+    }
+    let time = Duration::try_from_secs_f64(time_ms / 1000.0).context("the time is too large")?;
+    let delta = |index: usize| -> Result<i8> {
+        let delta = fields
+            .get(index)
+            .ok_or_else(|| anyhow!("expected a delta"))?;
+        delta.parse().context("invalid delta")
+    };
+    let motion = |index: usize| -> Result<i32> {
+        let motion = fields
+            .get(index)
+            .ok_or_else(|| anyhow!("expected a motion"))?;
+        motion.parse().context("invalid motion")
+    };
+    let scroll = |delta| TraceInput::Event(Event::Scroll(delta));
+    // Also returns the number of the fields.
+    let (event, len) = match fields.get(1).copied() {
+        Some("down") => (TraceInput::Event(Event::ButtonDown), 2),
+        Some("up") => (TraceInput::Event(Event::ButtonUp), 2),
+        Some("vertical") => (scroll(ScrollDelta::along(Axis::Vertical, delta(2)?)), 3),
+        Some("horizontal") => (scroll(ScrollDelta::along(Axis::Horizontal, delta(2)?)), 3),
+        Some("diagonal") => (
+            scroll(ScrollDelta {
+                dx: delta(2)?,
+                dy: delta(3)?,
+            }),
+            4,
+        ),
+        Some("motion") => (
+            TraceInput::Event(Event::Motion {
+                dx: motion(2)?,
+                dy: motion(3)?,
+            }),
+            4,
+        ),
+        Some("modifiers") => (
+            TraceInput::Modifiers(
+                fields
+                    .get(2)
+                    .ok_or_else(|| anyhow!("expected modifiers"))?
+                    .parse()?,
+            ),
+            3,
+        ),
+        Some(event) => bail!("unknown event `{}`", event),
+        None => bail!("expected `time_ms,event[,delta]`"),
+    };
+    if let Some(field) = fields.get(len) {
+        bail!("unexpected field `{}`", field);
+    }
+    Ok(TraceEvent { time, event })
+}
+
+pub fn load_trace(path: &Path) -> Result<Vec<TraceEvent>> {
+    let text = fs::read_to_string(path).with_context(|| format!("reading {:?}", path))?;
+    parse_trace(&text).with_context(|| format!("parsing {:?}", path))
+}
+
+/// A wheel event emitted by the controller.
+pub struct Output {
+    pub time: Duration,
+    pub wheel: WheelTick,
+    pub distance: i64,
+    pub latency: Duration,
+}
+
+#[derive(Default)]
+struct Recorder {
+    outputs: Vec<Output>,
+    distances: [i64; 2],
+    last_scroll: Duration,
+}
+
+impl Recorder {
+    fn record(&mut self, time: Duration, wheel: WheelTick) {
+        let distance = match wheel.axis {
+            Axis::Vertical => &mut self.distances[0],
+            Axis::Horizontal => &mut self.distances[1],
+        };
+        *distance += wheel.mouse_data as i64;
+        self.outputs.push(Output {
+            time,
+            wheel,
+            distance: *distance,
+            latency: time - self.last_scroll,
+        });
+    }
+}
+
+/// The controller and what it emitted.
+struct Simulation {
+    control: Box<dyn StepControl>,
+    tick_interval: Duration,
+    /// The ticks start at a scroll and stop when the controller no longer needs them.
+    next_tick: Option<Duration>,
+    recorder: Recorder,
+}
+
+impl Simulation {
+    fn perform(&mut self, time: Duration, actions: Vec<Action>) {
+        for action in actions {
+            match action {
+                // Only the wheel is simulated.
+                Action::Click(_)
+                | Action::ButtonDown(_)
+                | Action::ButtonUp(_)
+                | Action::Motion { .. } => {}
+                Action::Press => self.control.press(),
+                Action::Scroll(delta) => {
+                    self.recorder.last_scroll = time;
+                    for wheel in self.control.scroll(delta) {
+                        self.recorder.record(time, wheel);
+                    }
+                    self.next_tick = self.next_tick.or(Some(time + self.tick_interval));
+                }
+                // Some controllers keep scrolling. The others stop at the next tick.
+                Action::StopScroll => self.control.stop(),
+                Action::HaltScroll => self.control.halt(),
+                Action::Zoom(steps) => {
+                    self.recorder.last_scroll = time;
+                    let wheel = WheelTick {
+                        axis: Axis::Vertical,
+                        mouse_data: steps * WHEEL_DELTA,
+                    };
+                    self.recorder.record(time, wheel);
+                }
+            }
+        }
+    }
+
+    fn tick(&mut self, time: Duration) {
+        let wheels = self.control.tick();
+        self.next_tick = if wheels.is_empty() {
+            None
+        } else {
+            Some(time + self.tick_interval)
+        };
+        for wheel in wheels {
+            self.recorder.record(time, wheel);
+        }
+    }
+}
+
+/// What happens next. They happen in this order at the same time.
+#[derive(Eq, PartialEq, Ord, PartialOrd, Copy, Clone)]
+enum Step {
+    Event,
+    /// The deadline of [`TPMiddle`](crate::tpmiddle::TPMiddle).
+    Timeout,
+    Tick,
+}
+
+/// Feeds `trace` to the controller of `scroll`, ticking it like its real thread does.
+pub fn simulate(scroll: &ScrollConfig, trace: impl IntoIterator<Item = TraceEvent>) -> Vec<Output> {
+    let clock = ManualClock::new();
+    let origin = clock.now();
+    let mut tpmiddle = scroll.create_tpmiddle();
+    let mut simulation = Simulation {
+        control: scroll.create_stepper(clock.clone()),
+        tick_interval: scroll.tick_interval(),
+        next_tick: None,
+        recorder: Recorder::default(),
+    };
+    let mut trace = trace.into_iter().peekable();
+    let mut modifiers = Modifiers::default();
+
+    loop {
+        let event_time = trace.peek().map(|event| event.time);
+        let deadline = tpmiddle.deadline().map(|deadline| deadline - origin);
+        let next = [
+            (event_time, Step::Event),
+            (deadline, Step::Timeout),
+            (simulation.next_tick, Step::Tick),
+        ]
+        .iter()
+        .filter_map(|&(time, step)| Some((time?, step)))
+        .min();
+        let (time, step) = match next {
+            Some(next) => next,
+            None => break,
+        };
+
+        clock.advance(origin + time - clock.now());
+        match step {
+            Step::Event => match trace.next().unwrap().event {
+                TraceInput::Event(event) => {
+                    let actions = tpmiddle.handle(clock.now(), event, modifiers);
+                    simulation.perform(time, actions);
+                }
+                TraceInput::Modifiers(held) => modifiers = held,
+            },
+            Step::Timeout => {
+                let actions = tpmiddle.handle_timeout(clock.now());
+                simulation.perform(time, actions);
+            }
+            Step::Tick => simulation.tick(time),
+        }
+    }
+    simulation.recorder.outputs
+}
+
+pub fn write_csv(outputs: &[Output], mut writer: impl Write) -> io::Result<()> {
+    writeln!(writer, "time_ms,axis,mouse_data,distance,latency_ms")?;
+    for output in outputs {
+        let axis = match output.wheel.axis {
+            Axis::Vertical => "vertical",
+            Axis::Horizontal => "horizontal",
+        };
+        writeln!(
+            writer,
+            "{:.3},{},{},{},{:.3}",
+            output.time.as_secs_f64() * 1000.0,
+            axis,
+            output.wheel.mouse_data,
+            output.distance,
+            output.latency.as_secs_f64() * 1000.0
+        )?;
+    }
+    writer.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::control::ScrollControlType;
+    use crate::input::Modifier;
+
+    #[test]
+    fn rejects_invalid_times() {
+        for line in &[
+            "-1,down",
+            "NaN,down",
+            "inf,down",
+            "1e400,down",
+            "1e300,down",
+        ] {
+            assert!(parse_trace(line).is_err(), "{}", line);
+        }
+    }
+
+    #[test]
+    fn rejects_time_going_backwards() {
+        assert!(parse_trace("10,down\n5,up").is_err());
+        assert_eq!(parse_trace("10,down\n10,up").unwrap().len(), 2);
+    }
+
+    fn parse_error(text: &str) -> String {
+        format!("{:#}", parse_trace(text).err().unwrap())
+    }
+
+    #[test]
+    fn rejects_invalid_rows() {
+        assert_eq!(
+            parse_error("0,down\n5,vertical"),
+            "line 2: expected a delta"
+        );
+        assert_eq!(
+            parse_error("5,horizontal,x"),
+            "line 1: invalid delta: invalid digit found in string"
+        );
+        assert_eq!(parse_error("5,wheel,1"), "line 1: unknown event `wheel`");
+        assert_eq!(parse_error("5"), "line 1: expected `time_ms,event[,delta]`");
+        assert_eq!(parse_error("5,up,1"), "line 1: unexpected field `1`");
+        assert_eq!(
+            parse_error("5,vertical,1,2"),
+            "line 1: unexpected field `2`"
+        );
+    }
+
+    #[test]
+    fn parses_motion_and_modifiers() {
+        let trace =
+            parse_trace("0,motion,3,-12\n1,modifiers,ctrl + shift\n2,modifiers,none").unwrap();
+        match trace[0].event {
+            TraceInput::Event(Event::Motion { dx: 3, dy: -12 }) => {}
+            _ => panic!("not a motion"),
+        }
+        let mut ctrl_shift = Modifiers::default();
+        ctrl_shift.insert(Modifier::Ctrl);
+        ctrl_shift.insert(Modifier::Shift);
+        match trace[1].event {
+            TraceInput::Modifiers(modifiers) => assert_eq!(modifiers, ctrl_shift),
+            _ => panic!("not modifiers"),
+        }
+        match trace[2].event {
+            TraceInput::Modifiers(modifiers) => assert_eq!(modifiers, Modifiers::default()),
+            _ => panic!("not modifiers"),
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_motion() {
+        assert_eq!(parse_error("0,down\n5,motion"), "line 2: expected a motion");
+        assert_eq!(parse_error("5,motion,1"), "line 1: expected a motion");
+        assert_eq!(
+            parse_error("5,motion,1,x"),
+            "line 1: invalid motion: invalid digit found in string"
+        );
+        assert_eq!(
+            parse_error("5,motion,1,2,3"),
+            "line 1: unexpected field `3`"
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_modifiers() {
+        assert_eq!(parse_error("5,modifiers"), "line 1: expected modifiers");
+        assert_eq!(
+            parse_error("5,modifiers,hyper"),
+            "line 1: `hyper` is an invalid modifier"
+        );
+        assert_eq!(
+            parse_error("5,modifiers,ctrl+"),
+            "line 1: `` is an invalid modifier"
+        );
+        assert_eq!(
+            parse_error("5,modifiers,ctrl+shift,alt"),
+            "line 1: unexpected field `alt`"
+        );
+    }
+
+    const TRACE: &str = "\
+        # time_ms,event,delta
+        0,down
+        10,vertical,1
+        20,diagonal,-1,2
+        30.5,horizontal,1
+        40,up
+    ";
+
+    fn simulate_trace(control: ScrollControlType) -> Vec<Output> {
+        let scroll = ScrollConfig::from_sections(control, None, &[]).unwrap();
+        simulate(&scroll, parse_trace(TRACE).unwrap())
+    }
+
+    fn rows(outputs: &[Output]) -> Vec<(f64, Axis, i32, i64, f64)> {
+        let ms = |duration: Duration| (duration.as_secs_f64() * 1e6).round() / 1e3;
+        outputs
+            .iter()
+            .map(|output| {
+                (
+                    ms(output.time),
+                    output.wheel.axis,
+                    output.wheel.mouse_data,
+                    output.distance,
+                    ms(output.latency),
+                )
+            })
+            .collect()
+    }
+
+    fn csv(outputs: &[Output]) -> String {
+        let mut csv = Vec::new();
+        write_csv(outputs, &mut csv).unwrap();
+        String::from_utf8(csv).unwrap()
+    }
+
+    #[test]
+    fn simulates_classic() {
+        let outputs = simulate_trace(ScrollControlType::Classic);
+        assert_eq!(
+            rows(&outputs),
+            [
+                (10.0, Axis::Vertical, 120, 120, 0.0),
+                (20.0, Axis::Vertical, 240, 360, 0.0),
+                (20.0, Axis::Horizontal, -120, -120, 0.0),
+                (30.5, Axis::Horizontal, 120, 0, 0.0),
+            ]
+        );
+        assert_eq!(
+            csv(&outputs),
+            "time_ms,axis,mouse_data,distance,latency_ms\n\
+             10.000,vertical,120,120,0.000\n\
+             20.000,vertical,240,360,0.000\n\
+             20.000,horizontal,-120,-120,0.000\n\
+             30.500,horizontal,120,0,0.000\n"
+        );
+    }
+
+    #[test]
+    fn simulates_smooth() {
+        // It ticks at 120 Hz from the first scroll, and stops at the release.
+        let outputs = simulate_trace(ScrollControlType::Smooth);
+        assert_eq!(
+            rows(&outputs),
+            [
+                (18.333, Axis::Vertical, 0, 0, 8.333),
+                (26.667, Axis::Vertical, 34, 34, 6.667),
+                (26.667, Axis::Horizontal, -1, -1, 6.667),
+                (35.0, Axis::Vertical, 34, 68, 4.5),
+                (35.0, Axis::Horizontal, 0, -1, 4.5),
+            ]
+        );
+        assert_eq!(
+            csv(&outputs),
+            "time_ms,axis,mouse_data,distance,latency_ms\n\
+             18.333,vertical,0,0,8.333\n\
+             26.667,vertical,34,34,6.667\n\
+             26.667,horizontal,-1,-1,6.667\n\
+             35.000,vertical,34,68,4.500\n\
+             35.000,horizontal,0,-1,4.500\n"
+        );
+    }
+
+    #[test]
+    fn sticky_idle_timeout_halts_coasting() {
+        let coast = |config: &str| {
+            let sections = crate::config::parse(config).unwrap();
+            let scroll =
+                ScrollConfig::from_sections(ScrollControlType::Kinetic, None, &sections).unwrap();
+            let trace = parse_trace("0,down\n50,up\n100,motion,0,-40").unwrap();
+            let outputs = simulate(&scroll, trace);
+            outputs.last().unwrap().time
+        };
+        assert!(coast("[sticky]") > Duration::from_millis(300));
+        // The idle timeout is at 300 ms.
+        assert!(coast("[sticky]\nidle_timeout_ms = 200") <= Duration::from_millis(300));
+    }
+}
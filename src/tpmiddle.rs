@@ -1,85 +1,1515 @@
+use std::str::FromStr;
 use std::time::{Duration, Instant};
 
-use winapi::shared::minwindef::{LPARAM, UINT, WPARAM};
-use winapi::shared::windef::HWND;
-use winapi::um::winuser::{HRAWINPUT, MOUSEEVENTF_HWHEEL, MOUSEEVENTF_WHEEL, WM_INPUT};
+use anyhow::*;
 
-use crate::control::ScrollControl;
-use crate::hid::DeviceInfo;
-use crate::input::{send_click, Event, EventReader};
-use crate::window::{WindowProc, WindowProcError, WindowProcResult};
+use crate::config::{parse_int, Section};
+use crate::control::{AxisTransforms, Transformer};
+use crate::input::{Axis, Button, Event, Modifier, Modifiers, ScrollDelta};
 
 const MAX_MIDDLE_CLICK_DURATION: Duration = Duration::from_millis(500);
 
+/// What a short middle press emits.
+#[derive(Eq, PartialEq, Copy, Clone, Debug)]
+pub enum ClickAction {
+    Click(Button),
+    /// A double click of the left button.
+    DoubleClick,
+    Nothing,
+}
+
+impl ClickAction {
+    fn actions(self) -> Vec<Action> {
+        match self {
+            ClickAction::Click(button) => vec![Action::Click(button)],
+            ClickAction::DoubleClick => vec![Action::Click(Button::Left); 2],
+            ClickAction::Nothing => Vec::new(),
+        }
+    }
+}
+
+impl FromStr for ClickAction {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "middle" => Ok(ClickAction::Click(Button::Middle)),
+            "back" => Ok(ClickAction::Click(Button::X1)),
+            "forward" => Ok(ClickAction::Click(Button::X2)),
+            "double-click" => Ok(ClickAction::DoubleClick),
+            "none" => Ok(ClickAction::Nothing),
+            _ => Err(anyhow!("`{}` is an invalid click action", s)),
+        }
+    }
+}
+
+/// The click, long press and double press of the middle button.
+#[derive(Eq, PartialEq, Copy, Clone, Debug)]
+pub struct ClickParams {
+    pub action: ClickAction,
+    /// A press without scrolls up to this long is a click.
+    pub max_duration: Duration,
+    /// Emitted when the button is held this long without scrolls. Disabled if `None`.
+    pub long_press: Option<ClickAction>,
+    pub long_press_duration: Duration,
+    /// Emitted instead of two clicks within the window. Disabled if `None`.
+    /// If enabled, a click is emitted after the window passes without another press.
+    pub double_press: Option<ClickAction>,
+    pub double_press_window: Duration,
+}
+
+impl ClickParams {
+    pub(crate) fn from_section(section: &Section) -> Result<Self> {
+        section.check_keys(&[
+            "action",
+            "max_duration_ms",
+            "long_press",
+            "long_press_ms",
+            "double_press",
+            "double_press_window_ms",
+        ])?;
+        let default = Self::default();
+        Ok(Self {
+            action: section.get("action", str::parse)?.unwrap_or(default.action),
+            max_duration: section
+                .get("max_duration_ms", parse_int)?
+                .map_or(default.max_duration, Duration::from_millis),
+            long_press: section.get("long_press", str::parse)?,
+            long_press_duration: section
+                .get("long_press_ms", parse_int)?
+                .map_or(default.long_press_duration, Duration::from_millis),
+            double_press: section.get("double_press", str::parse)?,
+            double_press_window: section
+                .get("double_press_window_ms", parse_int)?
+                .map_or(default.double_press_window, Duration::from_millis),
+        })
+    }
+}
+
+impl Default for ClickParams {
+    fn default() -> Self {
+        Self {
+            action: ClickAction::Click(Button::Middle),
+            max_duration: MAX_MIDDLE_CLICK_DURATION,
+            long_press: None,
+            long_press_duration: MAX_MIDDLE_CLICK_DURATION,
+            double_press: None,
+            double_press_window: Duration::from_millis(300),
+        }
+    }
+}
+
 enum State {
     Idle,
-    MiddleDown { time: Instant },
-    Scroll,
+    MiddleDown {
+        time: Instant,
+        /// The press follows a click that waits for a double press.
+        after_click: bool,
+        /// The press ends the sticky scroll, so its click doesn't start another.
+        ends_sticky: bool,
+    },
+    /// Held past the long press duration without scrolls.
+    LongPressed,
+    /// A click that becomes a double press if another press follows until `until`.
+    ClickPending {
+        until: Instant,
+    },
+    Scroll {
+        lock: Option<AxisLock>,
+    },
+    /// The middle button is passed through. Scrolls move the pointer by `speed` pixels per unit.
+    Drag {
+        speed: i32,
+    },
+    /// Scrolls with the pointer motion until the next press.
+    Sticky(StickyScroll),
 }
 
-pub struct TPMiddle {
-    state: State,
-    control: Box<dyn ScrollControl>,
-    event_reader: EventReader<'static>,
+/// Toggles the scrolling by a click instead of holding the middle button. The pointer motion of
+/// the TrackPoint scrolls until the next press, or until it stops for the idle timeout.
+/// It takes the place of the click action.
+#[derive(Eq, PartialEq, Copy, Clone, Debug)]
+pub struct StickyParams {
+    pub idle_timeout: Duration,
+    /// Pixels of the pointer motion per scroll unit.
+    pub motion_per_unit: i32,
 }
 
-impl TPMiddle {
-    pub fn new(device_filter: &'static [DeviceInfo], control: Box<dyn ScrollControl>) -> Self {
-        TPMiddle {
-            state: State::Idle,
-            control,
-            event_reader: EventReader::new(device_filter),
+impl StickyParams {
+    pub(crate) fn from_section(section: &Section) -> Result<Self> {
+        section.check_keys(&["idle_timeout_ms", "motion_per_unit"])?;
+        let default = Self::default();
+        let params = Self {
+            idle_timeout: section
+                .get("idle_timeout_ms", parse_int)?
+                .map_or(default.idle_timeout, Duration::from_millis),
+            motion_per_unit: section
+                .get("motion_per_unit", parse_int)?
+                .unwrap_or(default.motion_per_unit),
+        };
+        if params.idle_timeout == Duration::from_millis(0) {
+            bail!("`idle_timeout_ms` should be positive");
+        }
+        if params.motion_per_unit == 0 {
+            bail!("`motion_per_unit` should be positive");
+        }
+        Ok(params)
+    }
+}
+
+impl Default for StickyParams {
+    fn default() -> Self {
+        Self {
+            idle_timeout: Duration::from_secs(3),
+            motion_per_unit: 8,
+        }
+    }
+}
+
+struct StickyScroll {
+    params: StickyParams,
+    last_motion: Instant,
+    /// Pointer motion short of a scroll unit, right and down.
+    remainder: [i32; 2],
+    axis_lock: Option<AxisLockParams>,
+    /// Starts at the first scroll like the one of a held scroll.
+    lock: Option<AxisLock>,
+}
+
+impl StickyScroll {
+    fn new(params: StickyParams, axis_lock: Option<AxisLockParams>, time: Instant) -> Self {
+        Self {
+            params,
+            last_motion: time,
+            remainder: [0; 2],
+            axis_lock,
+            lock: None,
+        }
+    }
+
+    fn deadline(&self) -> Instant {
+        self.last_motion + self.params.idle_timeout
+    }
+
+    /// Turns the pointer motion into a scroll.
+    fn motion(&mut self, time: Instant, dx: i32, dy: i32) -> ScrollDelta {
+        self.last_motion = time;
+        let per_unit = self.params.motion_per_unit;
+        let mut units = |index: usize, motion: i32| {
+            let remainder = &mut self.remainder[index];
+            *remainder += motion;
+            let units = *remainder / per_unit;
+            *remainder -= units * per_unit;
+            units.clamp(-(i8::MAX as i32), i8::MAX as i32) as i8
+        };
+        // Moving the pointer up scrolls up.
+        let delta = ScrollDelta {
+            dx: units(0, dx),
+            dy: -units(1, dy),
+        };
+        if delta.is_zero() {
+            return delta;
+        }
+        match self.axis_lock {
+            Some(params) => self
+                .lock
+                .get_or_insert_with(|| AxisLock::new(params, time))
+                .filter(time, delta),
+            None => delta,
+        }
+    }
+}
+
+/// What passes the middle press through.
+#[derive(Eq, PartialEq, Copy, Clone, Debug)]
+pub enum PassthroughTrigger {
+    Always,
+    /// The modifier held at the press.
+    Modifier(Modifier),
+}
+
+impl FromStr for PassthroughTrigger {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "always" => Ok(PassthroughTrigger::Always),
+            _ => s
+                .parse()
+                .map(PassthroughTrigger::Modifier)
+                .map_err(|_| anyhow!("`{}` is an invalid passthrough trigger", s)),
+        }
+    }
+}
+
+/// Passes the middle button through as a real one, and turns scrolls into pointer motion while
+/// it is held. It is the middle drag that CAD and 3D apps use to orbit and pan.
+#[derive(Eq, PartialEq, Copy, Clone, Debug)]
+pub struct PassthroughParams {
+    pub trigger: PassthroughTrigger,
+    /// Pixels of the pointer motion per scroll unit.
+    pub speed: i32,
+}
+
+impl PassthroughParams {
+    pub(crate) fn from_section(section: &Section) -> Result<Self> {
+        section.check_keys(&["trigger", "speed"])?;
+        let default = Self::default();
+        let params = Self {
+            trigger: section
+                .get("trigger", str::parse)?
+                .unwrap_or(default.trigger),
+            speed: section.get("speed", parse_int)?.unwrap_or(default.speed),
+        };
+        if params.speed == 0 {
+            bail!("`speed` should be positive");
+        }
+        Ok(params)
+    }
+
+    fn is_triggered(&self, modifiers: Modifiers) -> bool {
+        match self.trigger {
+            PassthroughTrigger::Always => true,
+            PassthroughTrigger::Modifier(modifier) => modifiers.contains(modifier),
+        }
+    }
+}
+
+impl Default for PassthroughParams {
+    fn default() -> Self {
+        Self {
+            trigger: PassthroughTrigger::Always,
+            speed: 4,
+        }
+    }
+}
+
+/// Locks the scroll to the dominant axis while the middle button is held.
+#[derive(Eq, PartialEq, Copy, Clone, Debug)]
+pub struct AxisLockParams {
+    /// The axis that scrolls more in this window since the first scroll is locked.
+    pub window: Duration,
+    /// Scroll units along the other axis, without scrolls along the locked axis in between, that
+    /// move the lock to the other axis. `0` never moves it.
+    pub break_threshold: u32,
+}
+
+impl AxisLockParams {
+    pub(crate) fn from_section(section: &Section) -> Result<Self> {
+        section.check_keys(&["window_ms", "break_threshold"])?;
+        let default = Self::default();
+        Ok(Self {
+            window: section
+                .get("window_ms", parse_int)?
+                .map_or(default.window, Duration::from_millis),
+            break_threshold: section
+                .get("break_threshold", parse_int)?
+                .unwrap_or(default.break_threshold),
+        })
+    }
+}
+
+impl Default for AxisLockParams {
+    fn default() -> Self {
+        Self {
+            window: Duration::from_millis(100),
+            break_threshold: 6,
         }
     }
 }
 
-impl WindowProc for TPMiddle {
-    fn proc(
-        &mut self,
-        _hwnd: HWND,
-        u_msg: UINT,
-        _w_param: WPARAM,
-        l_param: LPARAM,
-    ) -> WindowProcResult {
-        if u_msg != WM_INPUT {
-            return Err(WindowProcError::UnhandledMessage);
+struct AxisLock {
+    params: AxisLockParams,
+    since: Instant,
+    locked: Axis,
+    /// Scroll units of the vertical and horizontal axes in the window.
+    totals: [u32; 2],
+    off_axis: u32,
+}
+
+impl AxisLock {
+    fn new(params: AxisLockParams, time: Instant) -> Self {
+        Self {
+            params,
+            since: time,
+            locked: Axis::Vertical,
+            totals: [0; 2],
+            off_axis: 0,
         }
+    }
 
-        let events = if let Ok(events) = self.event_reader.read_from_raw_input(l_param as HRAWINPUT)
-        {
-            events
+    /// Returns the part of the scroll that passes the lock.
+    fn filter(&mut self, time: Instant, delta: ScrollDelta) -> ScrollDelta {
+        let other = match self.locked {
+            Axis::Vertical => Axis::Horizontal,
+            Axis::Horizontal => Axis::Vertical,
+        };
+        let units = |axis| delta.get(axis).unsigned_abs() as u32;
+        if time < self.since + self.params.window {
+            self.totals[0] += units(Axis::Vertical);
+            self.totals[1] += units(Axis::Horizontal);
+            let (locked_total, other_total) = match self.locked {
+                Axis::Vertical => (self.totals[0], self.totals[1]),
+                Axis::Horizontal => (self.totals[1], self.totals[0]),
+            };
+            if other_total > locked_total {
+                self.locked = other;
+            }
         } else {
-            return Ok(0);
+            if delta.get(self.locked) != 0 {
+                self.off_axis = 0;
+            }
+            self.off_axis += units(other);
+            if self.params.break_threshold != 0 && self.off_axis >= self.params.break_threshold {
+                self.locked = other;
+                self.off_axis = 0;
+            }
+        }
+        ScrollDelta::along(self.locked, delta.get(self.locked))
+    }
+}
+
+/// What a scroll does while a modifier key is held.
+#[derive(PartialEq, Copy, Clone, Debug)]
+pub enum ScrollRule {
+    /// Whole wheel notches that skip the scroll controller. Each is a zoom step with Ctrl held in
+    /// most apps, while the smooth controllers would emit a flood of partial notches.
+    Zoom,
+    /// Vertical scrolls scroll horizontally.
+    Horizontal,
+    /// Scales the scroll, like `0.25` for precise scrolls.
+    Multiplier(f32),
+}
+
+impl FromStr for ScrollRule {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "zoom" => Ok(ScrollRule::Zoom),
+            "horizontal" => Ok(ScrollRule::Horizontal),
+            _ => match s.parse::<f32>() {
+                Ok(multiplier) if multiplier.is_finite() && multiplier > 0.0 => {
+                    Ok(ScrollRule::Multiplier(multiplier))
+                }
+                _ => Err(anyhow!(
+                    "`{}` is not `zoom`, `horizontal` or a positive multiplier",
+                    s
+                )),
+            },
+        }
+    }
+}
+
+/// The rule table of the modifier keys. The rules of the held keys apply in the order of Ctrl,
+/// Shift and Alt, so Ctrl and Alt held together zoom precisely.
+#[derive(PartialEq, Copy, Clone, Debug)]
+pub struct ModifierRules {
+    pub ctrl: Option<ScrollRule>,
+    pub shift: Option<ScrollRule>,
+    pub alt: Option<ScrollRule>,
+    /// Scroll units per zoom step.
+    pub zoom_units_per_step: u32,
+}
+
+impl ModifierRules {
+    pub(crate) fn from_section(section: &Section) -> Result<Self> {
+        section.check_keys(&["ctrl", "shift", "alt", "zoom_units_per_step"])?;
+        let default = Self::default();
+        let rule = |key: &str, default: Option<ScrollRule>| {
+            section
+                .get(key, |value| match value {
+                    "none" => Ok(None),
+                    _ => Ok(Some(value.parse()?)),
+                })
+                .map(|rule| rule.unwrap_or(default))
+        };
+        let rules = Self {
+            ctrl: rule("ctrl", default.ctrl)?,
+            shift: rule("shift", default.shift)?,
+            alt: rule("alt", default.alt)?,
+            zoom_units_per_step: section
+                .get("zoom_units_per_step", parse_int)?
+                .unwrap_or(default.zoom_units_per_step),
         };
+        if rules.zoom_units_per_step == 0 {
+            bail!("`zoom_units_per_step` should be positive");
+        }
+        Ok(rules)
+    }
+
+    fn held(&self, modifiers: Modifiers) -> Vec<ScrollRule> {
+        [
+            (Modifier::Ctrl, self.ctrl),
+            (Modifier::Shift, self.shift),
+            (Modifier::Alt, self.alt),
+        ]
+        .iter()
+        .filter(|&&(modifier, _)| modifiers.contains(modifier))
+        .filter_map(|&(_, rule)| rule)
+        .collect()
+    }
+}
+
+impl Default for ModifierRules {
+    fn default() -> Self {
+        Self {
+            ctrl: Some(ScrollRule::Zoom),
+            shift: Some(ScrollRule::Horizontal),
+            alt: Some(ScrollRule::Multiplier(0.25)),
+            zoom_units_per_step: 3,
+        }
+    }
+}
+
+/// Rewrites scrolls by [`ModifierRules`] before the scroll controller.
+struct Rewriter {
+    rules: ModifierRules,
+    /// Fractions of the vertical and horizontal scrolls, and of the zoom step, not emitted yet.
+    remainders: [f32; 3],
+}
 
-        for event in events {
-            match event {
-                Event::ButtonDown => {
-                    self.state = State::MiddleDown {
-                        time: Instant::now(),
-                    };
+impl Rewriter {
+    fn new(rules: ModifierRules) -> Self {
+        Self {
+            rules,
+            remainders: [0.0; 3],
+        }
+    }
+
+    fn rewrite(&mut self, delta: ScrollDelta, modifiers: Modifiers) -> Option<Action> {
+        let rules = self.rules.held(modifiers);
+        if rules.is_empty() {
+            return Some(Action::Scroll(delta));
+        }
+
+        let mut zoom = false;
+        let mut vertical = delta.dy as f32;
+        let mut horizontal = delta.dx as f32;
+        for rule in rules {
+            match rule {
+                ScrollRule::Zoom => zoom = true,
+                ScrollRule::Horizontal => {
+                    horizontal += vertical;
+                    vertical = 0.0;
+                }
+                ScrollRule::Multiplier(multiplier) => {
+                    vertical *= multiplier;
+                    horizontal *= multiplier;
                 }
-                Event::ButtonUp => {
-                    self.control.stop();
-                    if let State::MiddleDown { time } = self.state {
-                        let now = Instant::now();
-                        if now <= time + MAX_MIDDLE_CLICK_DURATION {
-                            send_click(3);
+            }
+        }
+
+        if zoom {
+            let steps = vertical / self.rules.zoom_units_per_step as f32;
+            return match take_units(&mut self.remainders[2], steps) {
+                0 => None,
+                steps => Some(Action::Zoom(steps as i32)),
+            };
+        }
+        let delta = ScrollDelta {
+            dx: take_units(&mut self.remainders[1], horizontal),
+            dy: take_units(&mut self.remainders[0], vertical),
+        };
+        if delta.is_zero() {
+            None
+        } else {
+            Some(Action::Scroll(delta))
+        }
+    }
+
+    fn reset(&mut self) {
+        self.remainders = [0.0; 3];
+    }
+}
+
+/// Adds `value` to `remainder`, and takes the whole units out of it.
+fn take_units(remainder: &mut f32, value: f32) -> i8 {
+    if *remainder * value < 0.0 {
+        // Direction is changed. Discard the fraction.
+        *remainder = 0.0;
+    }
+    *remainder += value;
+    let units = remainder.trunc();
+    *remainder -= units;
+    // Saturates at the bounds of `i8`.
+    units as i8
+}
+
+#[derive(Eq, PartialEq, Copy, Clone, Debug)]
+pub enum Action {
+    Click(Button),
+    /// The middle button is pressed. A click or scrolls may follow.
+    Press,
+    Scroll(ScrollDelta),
+    /// The middle button is released.
+    StopScroll,
+    /// The scrolling ends without a release, and the coasting stops too.
+    HaltScroll,
+    ButtonDown(Button),
+    ButtonUp(Button),
+    /// Pointer motion in pixels, right and down.
+    Motion {
+        dx: i32,
+        dy: i32,
+    },
+    /// Wheel notches that skip the scroll controller. Positive zooms in.
+    Zoom(i32),
+}
+
+/// Tuning of [`TPMiddle`].
+#[derive(PartialEq, Copy, Clone, Debug, Default)]
+pub struct TPMiddleParams {
+    /// Applied to the scrolls before `modifier_rules`.
+    pub axes: AxisTransforms,
+    pub click: ClickParams,
+    /// Disabled if `None`.
+    pub axis_lock: Option<AxisLockParams>,
+    /// Disabled if `None`.
+    pub passthrough: Option<PassthroughParams>,
+    /// Disabled if `None`.
+    pub sticky: Option<StickyParams>,
+    /// Disabled if `None`.
+    pub modifier_rules: Option<ModifierRules>,
+}
+
+/// Platform-neutral middle button state machine.
+///
+/// It turns decoded [`Event`]s into [`Action`]s and leaves delivering them to the platform.
+pub struct TPMiddle {
+    params: TPMiddleParams,
+    state: State,
+    transformer: Transformer,
+    rewriter: Option<Rewriter>,
+    sticky_available: bool,
+}
+
+impl TPMiddle {
+    pub fn new() -> Self {
+        Self::with_params(TPMiddleParams::default())
+    }
+
+    pub fn with_params(params: TPMiddleParams) -> Self {
+        TPMiddle {
+            params,
+            state: State::Idle,
+            transformer: Transformer::new(params.axes),
+            rewriter: params.modifier_rules.map(Rewriter::new),
+            sticky_available: true,
+        }
+    }
+
+    /// Sets whether the pointer motion can be captured for the sticky scroll now. While it
+    /// cannot, a click emits the click action instead of starting the sticky scroll.
+    pub fn set_sticky_available(&mut self, available: bool) {
+        self.sticky_available = available;
+    }
+
+    /// Handles the event with the modifier keys held at the time.
+    pub fn handle(&mut self, time: Instant, event: Event, modifiers: Modifiers) -> Vec<Action> {
+        // The deadline may have passed without a timeout.
+        let mut actions = self.handle_timeout(time);
+        match event {
+            Event::ButtonDown => {
+                let after_click = matches!(self.state, State::ClickPending { .. });
+                let ends_sticky = matches!(self.state, State::Sticky(_));
+                if ends_sticky {
+                    actions.push(Action::StopScroll);
+                }
+                self.transformer.reset();
+                if let Some(rewriter) = &mut self.rewriter {
+                    rewriter.reset();
+                }
+                actions.push(Action::Press);
+                match self.params.passthrough {
+                    Some(passthrough) if passthrough.is_triggered(modifiers) => {
+                        if after_click {
+                            // Not a double press. Only the click before it is.
+                            actions.extend(self.click(time));
+                        }
+                        self.state = State::Drag {
+                            speed: passthrough.speed,
+                        };
+                        actions.push(Action::ButtonDown(Button::Middle));
+                    }
+                    _ => {
+                        self.state = State::MiddleDown {
+                            time,
+                            after_click,
+                            ends_sticky,
                         }
                     }
-                    self.state = State::Idle;
                 }
-                Event::Vertical(dy) => {
-                    self.state = State::Scroll;
-                    self.control.scroll(MOUSEEVENTF_WHEEL, dy);
+            }
+            Event::ButtonUp if matches!(self.state, State::Drag { .. }) => {
+                self.state = State::Idle;
+                actions.push(Action::ButtonUp(Button::Middle));
+            }
+            Event::ButtonUp => {
+                actions.push(Action::StopScroll);
+                actions.extend(self.release(time));
+            }
+            Event::Scroll(delta) => actions.extend(self.scroll(time, delta, modifiers)),
+            Event::Motion { dx, dy } => {
+                if let State::Sticky(ref mut sticky) = self.state {
+                    let delta = sticky.motion(time, dx, dy);
+                    actions.extend(self.rewrite(delta, modifiers));
                 }
-                Event::Horizontal(dx) => {
-                    self.state = State::Scroll;
-                    self.control.scroll(MOUSEEVENTF_HWHEEL, dx);
+            }
+        }
+        actions
+    }
+
+    /// Whether the pointer motion of the TrackPoint should be captured, and handled as
+    /// [`Event::Motion`] instead of moving the pointer.
+    pub fn captures_motion(&self) -> bool {
+        matches!(self.state, State::Sticky(_))
+    }
+
+    /// When [`handle_timeout`] should be called, if any.
+    ///
+    /// [`handle_timeout`]: Self::handle_timeout
+    pub fn deadline(&self) -> Option<Instant> {
+        let click = &self.params.click;
+        match self.state {
+            State::MiddleDown { time, .. } if click.long_press.is_some() => {
+                Some(time + click.long_press_duration)
+            }
+            State::ClickPending { until } => Some(until),
+            State::Sticky(ref sticky) => Some(sticky.deadline()),
+            _ => None,
+        }
+    }
+
+    /// Emits the long press or the pending click, or ends the idle sticky scroll if the deadline
+    /// has passed.
+    pub fn handle_timeout(&mut self, time: Instant) -> Vec<Action> {
+        match self.deadline() {
+            Some(deadline) if deadline <= time => {}
+            _ => return Vec::new(),
+        }
+        match (&self.state, self.params.click.long_press) {
+            (&State::MiddleDown { after_click, .. }, Some(long_press)) => {
+                let mut actions = Vec::new();
+                if after_click {
+                    actions.extend(self.click(time));
                 }
+                actions.extend(long_press.actions());
+                self.state = State::LongPressed;
+                actions
+            }
+            (State::ClickPending { .. }, _) => {
+                self.state = State::Idle;
+                self.click(time)
+            }
+            (State::Sticky(_), _) => {
+                self.state = State::Idle;
+                vec![Action::HaltScroll]
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    fn release(&mut self, time: Instant) -> Vec<Action> {
+        let click = self.params.click;
+        let state = std::mem::replace(&mut self.state, State::Idle);
+        let (down, after_click) = match state {
+            State::MiddleDown {
+                time,
+                after_click,
+                ends_sticky: false,
+            } => (time, after_click),
+            _ => return Vec::new(),
+        };
+        if time > down + click.max_duration {
+            // Not a double press. Only the click before it is.
+            return if after_click {
+                self.click(time)
+            } else {
+                Vec::new()
+            };
+        }
+        match click.double_press {
+            Some(double_press) if after_click => double_press.actions(),
+            Some(_) => {
+                self.state = State::ClickPending {
+                    until: time + click.double_press_window,
+                };
+                Vec::new()
+            }
+            None => self.click(time),
+        }
+    }
+
+    /// Emits the click action, or starts the sticky scroll instead if it is enabled.
+    fn click(&mut self, time: Instant) -> Vec<Action> {
+        match self.params.sticky {
+            Some(sticky) if self.sticky_available => {
+                self.state = State::Sticky(StickyScroll::new(sticky, self.params.axis_lock, time));
+                Vec::new()
             }
+            _ => self.params.click.action.actions(),
+        }
+    }
+
+    /// Applies the axis transforms, and then [`ModifierRules`] if any, to the scroll.
+    fn rewrite(&mut self, delta: ScrollDelta, modifiers: Modifiers) -> Option<Action> {
+        if delta.is_zero() {
+            return None;
+        }
+        let delta = self.transformer.apply(delta);
+        if delta.is_zero() {
+            return None;
+        }
+        match &mut self.rewriter {
+            Some(rewriter) => rewriter.rewrite(delta, modifiers),
+            None => Some(Action::Scroll(delta)),
+        }
+    }
+
+    fn scroll(&mut self, time: Instant, delta: ScrollDelta, modifiers: Modifiers) -> Vec<Action> {
+        if let State::Drag { speed } = self.state {
+            // Pushing the TrackPoint up scrolls up, and moves the pointer up.
+            return vec![Action::Motion {
+                dx: delta.dx as i32 * speed,
+                dy: -(delta.dy as i32) * speed,
+            }];
+        }
+
+        let mut actions = Vec::new();
+        let after_click = matches!(
+            self.state,
+            State::MiddleDown {
+                after_click: true,
+                ..
+            } | State::ClickPending { .. }
+        );
+        if after_click {
+            // Not a double press. Only the click before it is.
+            actions.extend(self.click(time));
         }
+        if !matches!(self.state, State::Scroll { .. }) {
+            self.state = State::Scroll {
+                lock: self
+                    .params
+                    .axis_lock
+                    .map(|params| AxisLock::new(params, time)),
+            };
+        }
+        let delta = match self.state {
+            State::Scroll {
+                lock: Some(ref mut lock),
+            } => lock.filter(time, delta),
+            _ => delta,
+        };
+        actions.extend(self.rewrite(delta, modifiers));
+        actions
+    }
+}
+
+impl Default for TPMiddle {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NO_MODIFIERS: Modifiers = Modifiers {
+        ctrl: false,
+        shift: false,
+        alt: false,
+    };
+
+    /// Feeds `(time_ms, event)` and returns the actions of each.
+    fn run(tpmiddle: &mut TPMiddle, events: Vec<(u64, Event)>) -> Vec<Vec<Action>> {
+        let events = events
+            .into_iter()
+            .map(|(time_ms, event)| (time_ms, event, NO_MODIFIERS))
+            .collect();
+        run_with(tpmiddle, events)
+    }
+
+    /// Feeds `(time_ms, event, modifiers)` and returns the actions of each.
+    fn run_with(tpmiddle: &mut TPMiddle, events: Vec<(u64, Event, Modifiers)>) -> Vec<Vec<Action>> {
+        let origin = Instant::now();
+        events
+            .into_iter()
+            .map(|(time_ms, event, modifiers)| {
+                let time = origin + Duration::from_millis(time_ms);
+                tpmiddle.handle(time, event, modifiers)
+            })
+            .collect()
+    }
+
+    fn vertical(delta: i8) -> Event {
+        Event::Scroll(ScrollDelta::along(Axis::Vertical, delta))
+    }
+
+    fn horizontal(delta: i8) -> Event {
+        Event::Scroll(ScrollDelta::along(Axis::Horizontal, delta))
+    }
+
+    fn scroll(dx: i8, dy: i8) -> Action {
+        Action::Scroll(ScrollDelta { dx, dy })
+    }
+
+    #[test]
+    fn click_within_max_duration() {
+        let mut tpmiddle = TPMiddle::new();
+        let actions = run(
+            &mut tpmiddle,
+            vec![(0, Event::ButtonDown), (100, Event::ButtonUp)],
+        );
+        assert_eq!(
+            actions,
+            vec![
+                vec![Action::Press],
+                vec![Action::StopScroll, Action::Click(Button::Middle)],
+            ]
+        );
+    }
+
+    #[test]
+    fn press_past_max_duration_is_not_click() {
+        let mut tpmiddle = TPMiddle::new();
+        let actions = run(
+            &mut tpmiddle,
+            vec![(0, Event::ButtonDown), (501, Event::ButtonUp)],
+        );
+        assert_eq!(actions, vec![vec![Action::Press], vec![Action::StopScroll]]);
+    }
+
+    #[test]
+    fn scroll_then_release() {
+        let mut tpmiddle = TPMiddle::new();
+        let actions = run(
+            &mut tpmiddle,
+            vec![
+                (0, Event::ButtonDown),
+                (10, vertical(1)),
+                (20, horizontal(-2)),
+                (100, Event::ButtonUp),
+            ],
+        );
+        assert_eq!(
+            actions,
+            vec![
+                vec![Action::Press],
+                vec![scroll(0, 1)],
+                vec![scroll(-2, 0)],
+                vec![Action::StopScroll],
+            ]
+        );
+    }
+
+    #[test]
+    fn stop_scroll_comes_before_click() {
+        // The controller stops before anything else is emitted at the release.
+        let mut tpmiddle = TPMiddle::new();
+        let actions = run(
+            &mut tpmiddle,
+            vec![
+                (0, Event::ButtonDown),
+                (10, Event::ButtonUp),
+                (20, Event::ButtonDown),
+                (30, vertical(1)),
+                (40, Event::ButtonUp),
+            ],
+        );
+        assert_eq!(actions[1][0], Action::StopScroll);
+        assert_eq!(actions[4], vec![Action::StopScroll]);
+    }
+
+    #[test]
+    fn transforms_scrolls() {
+        let mut axes = AxisTransforms::default();
+        axes.swap();
+        axes.vertical.multiplier = 0.5;
+        let mut tpmiddle = TPMiddle::with_params(TPMiddleParams {
+            axes,
+            ..TPMiddleParams::default()
+        });
+        let actions = run(
+            &mut tpmiddle,
+            vec![
+                (0, Event::ButtonDown),
+                (10, horizontal(2)),
+                (20, vertical(1)),
+                (30, Event::ButtonUp),
+                // The fraction does not carry over to the next press.
+                (40, Event::ButtonDown),
+                (50, vertical(1)),
+                (60, vertical(1)),
+            ],
+        );
+        assert_eq!(
+            actions,
+            vec![
+                vec![Action::Press],
+                vec![scroll(0, 2)],
+                vec![],
+                vec![Action::StopScroll],
+                vec![Action::Press],
+                vec![],
+                vec![scroll(1, 0)],
+            ]
+        );
+    }
+
+    #[test]
+    fn axis_lock_locks_the_dominant_axis() {
+        let mut tpmiddle = TPMiddle::with_params(TPMiddleParams {
+            axis_lock: Some(AxisLockParams::default()),
+            ..TPMiddleParams::default()
+        });
+        let actions = run(
+            &mut tpmiddle,
+            vec![
+                (0, Event::ButtonDown),
+                (10, Event::Scroll(ScrollDelta { dx: 2, dy: 1 })),
+                // Within the window, the vertical total doesn't exceed the horizontal one yet.
+                (20, vertical(1)),
+                (150, Event::Scroll(ScrollDelta { dx: -1, dy: 1 })),
+            ],
+        );
+        assert_eq!(
+            actions,
+            vec![
+                vec![Action::Press],
+                vec![scroll(2, 0)],
+                vec![],
+                vec![scroll(-1, 0)],
+            ]
+        );
+    }
+
+    #[test]
+    fn axis_lock_breaks_past_threshold() {
+        let mut tpmiddle = TPMiddle::with_params(TPMiddleParams {
+            axis_lock: Some(AxisLockParams {
+                window: Duration::from_millis(100),
+                break_threshold: 3,
+            }),
+            ..TPMiddleParams::default()
+        });
+        let actions = run(
+            &mut tpmiddle,
+            vec![
+                (0, Event::ButtonDown),
+                (10, vertical(1)),
+                (200, horizontal(1)),
+                // A scroll along the locked axis resets the count.
+                (210, vertical(1)),
+                (220, horizontal(2)),
+                (230, horizontal(1)),
+                (240, horizontal(1)),
+                (250, vertical(1)),
+            ],
+        );
+        assert_eq!(
+            actions,
+            vec![
+                vec![Action::Press],
+                vec![scroll(0, 1)],
+                vec![],
+                vec![scroll(0, 1)],
+                vec![],
+                vec![scroll(1, 0)],
+                vec![scroll(1, 0)],
+                vec![],
+            ]
+        );
+    }
+
+    #[test]
+    fn axis_lock_restarts_at_each_press() {
+        let mut tpmiddle = TPMiddle::with_params(TPMiddleParams {
+            axis_lock: Some(AxisLockParams::default()),
+            ..TPMiddleParams::default()
+        });
+        let actions = run(
+            &mut tpmiddle,
+            vec![
+                (0, Event::ButtonDown),
+                (10, horizontal(1)),
+                (20, Event::ButtonUp),
+                (1000, Event::ButtonDown),
+                (1010, vertical(1)),
+            ],
+        );
+        assert_eq!(actions[1], vec![scroll(1, 0)]);
+        assert_eq!(actions[4], vec![scroll(0, 1)]);
+    }
+
+    fn at(origin: Instant, time_ms: u64) -> Instant {
+        origin + Duration::from_millis(time_ms)
+    }
+
+    #[test]
+    fn long_press_at_deadline() {
+        let mut tpmiddle = TPMiddle::with_params(TPMiddleParams {
+            click: ClickParams {
+                long_press: Some(ClickAction::Click(Button::X1)),
+                ..ClickParams::default()
+            },
+            ..TPMiddleParams::default()
+        });
+        let origin = Instant::now();
+        assert_eq!(tpmiddle.deadline(), None);
+        tpmiddle.handle(origin, Event::ButtonDown, NO_MODIFIERS);
+        assert_eq!(tpmiddle.deadline(), Some(at(origin, 500)));
+        assert_eq!(tpmiddle.handle_timeout(at(origin, 499)), vec![]);
+        assert_eq!(
+            tpmiddle.handle_timeout(at(origin, 500)),
+            vec![Action::Click(Button::X1)]
+        );
+        assert_eq!(tpmiddle.deadline(), None);
+        // The release emits no click after the long press.
+        assert_eq!(
+            tpmiddle.handle(at(origin, 600), Event::ButtonUp, NO_MODIFIERS),
+            vec![Action::StopScroll]
+        );
+    }
+
+    #[test]
+    fn long_press_is_canceled_by_scroll() {
+        let mut tpmiddle = TPMiddle::with_params(TPMiddleParams {
+            click: ClickParams {
+                long_press: Some(ClickAction::Click(Button::X1)),
+                ..ClickParams::default()
+            },
+            ..TPMiddleParams::default()
+        });
+        let origin = Instant::now();
+        tpmiddle.handle(origin, Event::ButtonDown, NO_MODIFIERS);
+        tpmiddle.handle(at(origin, 100), vertical(1), NO_MODIFIERS);
+        assert_eq!(tpmiddle.deadline(), None);
+        assert_eq!(tpmiddle.handle_timeout(at(origin, 1000)), vec![]);
+    }
+
+    #[test]
+    fn long_press_past_deadline_without_timeout() {
+        // A late timer doesn't lose the long press. The next event emits it first.
+        let mut tpmiddle = TPMiddle::with_params(TPMiddleParams {
+            click: ClickParams {
+                long_press: Some(ClickAction::Click(Button::X1)),
+                ..ClickParams::default()
+            },
+            ..TPMiddleParams::default()
+        });
+        let actions = run(
+            &mut tpmiddle,
+            vec![(0, Event::ButtonDown), (700, Event::ButtonUp)],
+        );
+        assert_eq!(
+            actions,
+            vec![
+                vec![Action::Press],
+                vec![Action::Click(Button::X1), Action::StopScroll],
+            ]
+        );
+    }
+
+    fn double_press_params() -> TPMiddleParams {
+        TPMiddleParams {
+            click: ClickParams {
+                double_press: Some(ClickAction::DoubleClick),
+                ..ClickParams::default()
+            },
+            ..TPMiddleParams::default()
+        }
+    }
+
+    #[test]
+    fn double_press_within_window() {
+        let mut tpmiddle = TPMiddle::with_params(double_press_params());
+        let actions = run(
+            &mut tpmiddle,
+            vec![
+                (0, Event::ButtonDown),
+                (50, Event::ButtonUp),
+                (200, Event::ButtonDown),
+                (250, Event::ButtonUp),
+            ],
+        );
+        let double_click = vec![Action::Click(Button::Left); 2];
+        assert_eq!(
+            actions,
+            vec![
+                vec![Action::Press],
+                vec![Action::StopScroll],
+                vec![Action::Press],
+                [vec![Action::StopScroll], double_click].concat(),
+            ]
+        );
+        assert_eq!(tpmiddle.deadline(), None);
+    }
+
+    #[test]
+    fn pending_click_at_deadline() {
+        let mut tpmiddle = TPMiddle::with_params(double_press_params());
+        let origin = Instant::now();
+        tpmiddle.handle(origin, Event::ButtonDown, NO_MODIFIERS);
+        tpmiddle.handle(at(origin, 50), Event::ButtonUp, NO_MODIFIERS);
+        // The window starts at the release.
+        assert_eq!(tpmiddle.deadline(), Some(at(origin, 350)));
+        assert_eq!(tpmiddle.handle_timeout(at(origin, 349)), vec![]);
+        assert_eq!(
+            tpmiddle.handle_timeout(at(origin, 350)),
+            vec![Action::Click(Button::Middle)]
+        );
+        assert_eq!(tpmiddle.deadline(), None);
+    }
+
+    #[test]
+    fn scroll_after_click_emits_pending_click() {
+        let mut tpmiddle = TPMiddle::with_params(double_press_params());
+        let actions = run(
+            &mut tpmiddle,
+            vec![
+                (0, Event::ButtonDown),
+                (50, Event::ButtonUp),
+                (100, Event::ButtonDown),
+                (150, vertical(1)),
+            ],
+        );
+        assert_eq!(
+            actions[3],
+            vec![
+                Action::Click(Button::Middle),
+                Action::Scroll(ScrollDelta { dx: 0, dy: 1 }),
+            ]
+        );
+    }
+
+    #[test]
+    fn drag_passes_the_middle_button_through() {
+        let mut tpmiddle = TPMiddle::with_params(TPMiddleParams {
+            passthrough: Some(PassthroughParams::default()),
+            ..TPMiddleParams::default()
+        });
+        let actions = run(
+            &mut tpmiddle,
+            vec![
+                (0, Event::ButtonDown),
+                (10, Event::Scroll(ScrollDelta { dx: -1, dy: 2 })),
+                (100, Event::ButtonUp),
+            ],
+        );
+        assert_eq!(
+            actions,
+            vec![
+                vec![Action::Press, Action::ButtonDown(Button::Middle)],
+                vec![Action::Motion { dx: -4, dy: -8 }],
+                vec![Action::ButtonUp(Button::Middle)],
+            ]
+        );
+    }
+
+    #[test]
+    fn drag_needs_the_trigger_modifier() {
+        let mut tpmiddle = TPMiddle::with_params(TPMiddleParams {
+            passthrough: Some(PassthroughParams {
+                trigger: PassthroughTrigger::Modifier(Modifier::Shift),
+                speed: 2,
+            }),
+            ..TPMiddleParams::default()
+        });
+        let origin = Instant::now();
+        let shift = Modifiers {
+            shift: true,
+            ..NO_MODIFIERS
+        };
+        assert_eq!(
+            tpmiddle.handle(origin, Event::ButtonDown, NO_MODIFIERS),
+            vec![Action::Press]
+        );
+        assert_eq!(
+            tpmiddle.handle(at(origin, 10), Event::ButtonUp, NO_MODIFIERS),
+            vec![Action::StopScroll, Action::Click(Button::Middle)]
+        );
+        assert_eq!(
+            tpmiddle.handle(at(origin, 1000), Event::ButtonDown, shift),
+            vec![Action::Press, Action::ButtonDown(Button::Middle)]
+        );
+        // The modifier only matters at the press.
+        assert_eq!(
+            tpmiddle.handle(at(origin, 1010), vertical(1), NO_MODIFIERS),
+            vec![Action::Motion { dx: 0, dy: -2 }]
+        );
+    }
+
+    #[test]
+    fn drag_after_click_emits_pending_click() {
+        let mut tpmiddle = TPMiddle::with_params(TPMiddleParams {
+            passthrough: Some(PassthroughParams {
+                trigger: PassthroughTrigger::Modifier(Modifier::Shift),
+                ..PassthroughParams::default()
+            }),
+            ..double_press_params()
+        });
+        let origin = Instant::now();
+        let shift = Modifiers {
+            shift: true,
+            ..NO_MODIFIERS
+        };
+        tpmiddle.handle(origin, Event::ButtonDown, NO_MODIFIERS);
+        tpmiddle.handle(at(origin, 10), Event::ButtonUp, NO_MODIFIERS);
+        assert_eq!(
+            tpmiddle.handle(at(origin, 100), Event::ButtonDown, shift),
+            vec![
+                Action::Press,
+                Action::Click(Button::Middle),
+                Action::ButtonDown(Button::Middle),
+            ]
+        );
+        assert_eq!(tpmiddle.deadline(), None);
+    }
+
+    fn section(text: &str) -> Section {
+        crate::config::parse(text).unwrap().remove(0)
+    }
+
+    #[test]
+    fn passthrough_section() {
+        let params =
+            PassthroughParams::from_section(&section("[passthrough]\ntrigger = alt\nspeed = 2"))
+                .unwrap();
+        assert_eq!(params.trigger, PassthroughTrigger::Modifier(Modifier::Alt));
+        assert_eq!(params.speed, 2);
+        assert!(PassthroughParams::from_section(&section("[passthrough]\nspeed = 0")).is_err());
+        assert!(PassthroughParams::from_section(&section("[passthrough]\nspeed = -1")).is_err());
+    }
+
+    #[test]
+    fn sticky_section() {
+        let params = StickyParams::from_section(&section(
+            "[sticky]\nidle_timeout_ms = 500\nmotion_per_unit = 4",
+        ))
+        .unwrap();
+        assert_eq!(params.idle_timeout, Duration::from_millis(500));
+        assert_eq!(params.motion_per_unit, 4);
+        assert!(StickyParams::from_section(&section("[sticky]\nidle_timeout_ms = 0")).is_err());
+        assert!(StickyParams::from_section(&section("[sticky]\nmotion_per_unit = 0")).is_err());
+    }
+
+    fn sticky_params() -> TPMiddleParams {
+        TPMiddleParams {
+            sticky: Some(StickyParams::default()),
+            ..TPMiddleParams::default()
+        }
+    }
+
+    fn motion(dx: i32, dy: i32) -> Event {
+        Event::Motion { dx, dy }
+    }
+
+    #[test]
+    fn sticky_scrolls_by_motion_until_press() {
+        let mut tpmiddle = TPMiddle::with_params(sticky_params());
+        let actions = run(
+            &mut tpmiddle,
+            vec![
+                (0, Event::ButtonDown),
+                (50, Event::ButtonUp),
+                // 8 pixels per unit. Moving up scrolls up.
+                (100, motion(3, -10)),
+                (110, motion(6, -6)),
+                (200, Event::ButtonDown),
+                (250, Event::ButtonUp),
+                (300, motion(0, -8)),
+            ],
+        );
+        assert_eq!(
+            actions,
+            vec![
+                vec![Action::Press],
+                vec![Action::StopScroll],
+                vec![scroll(0, 1)],
+                vec![scroll(1, 1)],
+                vec![Action::StopScroll, Action::Press],
+                // The press that ends it doesn't start another.
+                vec![Action::StopScroll],
+                vec![],
+            ]
+        );
+        assert!(!tpmiddle.captures_motion());
+    }
+
+    #[test]
+    fn sticky_ends_at_idle_timeout() {
+        let mut tpmiddle = TPMiddle::with_params(sticky_params());
+        let origin = Instant::now();
+        tpmiddle.handle(origin, Event::ButtonDown, NO_MODIFIERS);
+        tpmiddle.handle(at(origin, 50), Event::ButtonUp, NO_MODIFIERS);
+        assert!(tpmiddle.captures_motion());
+        assert_eq!(tpmiddle.deadline(), Some(at(origin, 3050)));
+        // The motion postpones the deadline.
+        tpmiddle.handle(at(origin, 1000), motion(0, 1), NO_MODIFIERS);
+        assert_eq!(tpmiddle.deadline(), Some(at(origin, 4000)));
+        assert_eq!(tpmiddle.handle_timeout(at(origin, 3999)), vec![]);
+        assert_eq!(
+            tpmiddle.handle_timeout(at(origin, 4000)),
+            vec![Action::HaltScroll]
+        );
+        assert!(!tpmiddle.captures_motion());
+    }
+
+    #[test]
+    fn sticky_falls_back_to_click() {
+        let mut tpmiddle = TPMiddle::with_params(sticky_params());
+        tpmiddle.set_sticky_available(false);
+        let actions = run(
+            &mut tpmiddle,
+            vec![(0, Event::ButtonDown), (50, Event::ButtonUp)],
+        );
+        assert_eq!(
+            actions[1],
+            vec![Action::StopScroll, Action::Click(Button::Middle)]
+        );
+        assert!(!tpmiddle.captures_motion());
+    }
+
+    fn modifier_params(axes: AxisTransforms) -> TPMiddleParams {
+        TPMiddleParams {
+            axes,
+            modifier_rules: Some(ModifierRules::default()),
+            ..TPMiddleParams::default()
+        }
+    }
+
+    fn scroll_with(
+        tpmiddle: &mut TPMiddle,
+        delta: ScrollDelta,
+        modifiers: Modifiers,
+    ) -> Vec<Action> {
+        let origin = Instant::now();
+        tpmiddle.handle(origin, Event::ButtonDown, NO_MODIFIERS);
+        tpmiddle.handle(at(origin, 10), Event::Scroll(delta), modifiers)
+    }
+
+    const CTRL: Modifiers = Modifiers {
+        ctrl: true,
+        shift: false,
+        alt: false,
+    };
+    const SHIFT: Modifiers = Modifiers {
+        ctrl: false,
+        shift: true,
+        alt: false,
+    };
+    const ALT: Modifiers = Modifiers {
+        ctrl: false,
+        shift: false,
+        alt: true,
+    };
+
+    #[test]
+    fn shift_scrolls_horizontally_with_swapped_axes() {
+        let mut axes = AxisTransforms::default();
+        axes.swap();
+        let mut tpmiddle = TPMiddle::with_params(modifier_params(axes));
+        assert_eq!(
+            scroll_with(&mut tpmiddle, ScrollDelta { dx: 0, dy: 1 }, SHIFT),
+            vec![scroll(1, 0)]
+        );
+        assert_eq!(
+            scroll_with(&mut tpmiddle, ScrollDelta { dx: 1, dy: 0 }, SHIFT),
+            vec![scroll(1, 0)]
+        );
+        assert_eq!(
+            scroll_with(&mut tpmiddle, ScrollDelta { dx: 1, dy: 0 }, NO_MODIFIERS),
+            vec![scroll(0, 1)]
+        );
+    }
+
+    #[test]
+    fn zoom_follows_natural_scrolling() {
+        let mut tpmiddle = TPMiddle::with_params(modifier_params(AxisTransforms::default()));
+        assert_eq!(
+            scroll_with(&mut tpmiddle, ScrollDelta { dx: 0, dy: 3 }, CTRL),
+            vec![Action::Zoom(1)]
+        );
+
+        let mut axes = AxisTransforms::default();
+        axes.invert();
+        let mut tpmiddle = TPMiddle::with_params(modifier_params(axes));
+        assert_eq!(
+            scroll_with(&mut tpmiddle, ScrollDelta { dx: 0, dy: 3 }, CTRL),
+            vec![Action::Zoom(-1)]
+        );
+    }
+
+    #[test]
+    fn transforms_apply_before_modifier_rules() {
+        // The tripled unit makes a whole zoom step. Transforming the zoom instead would neither
+        // multiply nor invert it.
+        let mut axes = AxisTransforms::default();
+        axes.vertical.multiplier = 3.0;
+        axes.vertical.invert = true;
+        let mut tpmiddle = TPMiddle::with_params(modifier_params(axes));
+        assert_eq!(
+            scroll_with(&mut tpmiddle, ScrollDelta { dx: 0, dy: 1 }, CTRL),
+            vec![Action::Zoom(-1)]
+        );
+        assert_eq!(
+            scroll_with(&mut tpmiddle, ScrollDelta { dx: 0, dy: -2 }, CTRL),
+            vec![Action::Zoom(2)]
+        );
+    }
+
+    #[test]
+    fn zoom_takes_whole_steps() {
+        let mut tpmiddle = TPMiddle::with_params(modifier_params(AxisTransforms::default()));
+        let actions = run_with(
+            &mut tpmiddle,
+            vec![
+                (0, Event::ButtonDown, NO_MODIFIERS),
+                (10, vertical(2), CTRL),
+                (20, vertical(2), CTRL),
+                (30, vertical(-1), CTRL),
+                (40, vertical(-3), CTRL),
+            ],
+        );
+        assert_eq!(
+            actions,
+            vec![
+                vec![Action::Press],
+                vec![],
+                vec![Action::Zoom(1)],
+                // The reversal discards the fraction.
+                vec![],
+                vec![Action::Zoom(-1)],
+            ]
+        );
+    }
 
-        Ok(0)
+    #[test]
+    fn alt_scrolls_precisely() {
+        let mut tpmiddle = TPMiddle::with_params(modifier_params(AxisTransforms::default()));
+        let actions = run_with(
+            &mut tpmiddle,
+            vec![
+                (0, Event::ButtonDown, NO_MODIFIERS),
+                (10, vertical(2), ALT),
+                (20, vertical(2), ALT),
+                (30, vertical(1), NO_MODIFIERS),
+                // Ctrl and Alt zoom precisely.
+                (40, vertical(12), Modifiers { alt: true, ..CTRL }),
+            ],
+        );
+        assert_eq!(
+            actions,
+            vec![
+                vec![Action::Press],
+                vec![],
+                vec![scroll(0, 1)],
+                vec![scroll(0, 1)],
+                vec![Action::Zoom(1)],
+            ]
+        );
     }
 }
@@ -0,0 +1,364 @@
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::str::FromStr;
+
+use anyhow::*;
+use log::*;
+
+use crate::hid::{DeviceInfo, Transport};
+use crate::registry::{DeviceModel, Registry};
+
+/// Connects to a keyboard model. Implemented by each platform.
+pub trait Connector {
+    type Connection;
+
+    fn connect(&mut self, model: &'static DeviceModel) -> Result<Self::Connection>;
+}
+
+/// Which transports to connect over, and in which order.
+#[derive(Eq, PartialEq, Copy, Clone, Debug)]
+pub enum TransportPolicy {
+    /// Bluetooth, then USB.
+    Auto,
+    PreferUSB,
+    USBOnly,
+    BTOnly,
+}
+
+impl FromStr for TransportPolicy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "auto" => Ok(TransportPolicy::Auto),
+            "prefer-usb" => Ok(TransportPolicy::PreferUSB),
+            "usb-only" => Ok(TransportPolicy::USBOnly),
+            "bt-only" => Ok(TransportPolicy::BTOnly),
+            _ => Err(anyhow!("`{}` is an invalid transport policy", s)),
+        }
+    }
+}
+
+impl TransportPolicy {
+    /// Allowed transports from the most preferred one.
+    pub fn transports(&self) -> &'static [Transport] {
+        match self {
+            TransportPolicy::Auto => &[Transport::BT, Transport::USB],
+            TransportPolicy::PreferUSB => &[Transport::USB, Transport::BT],
+            TransportPolicy::USBOnly => &[Transport::USB],
+            TransportPolicy::BTOnly => &[Transport::BT],
+        }
+    }
+
+    pub fn allows(&self, transport: Transport) -> bool {
+        self.transports().contains(&transport)
+    }
+
+    /// Tests whether `transport` is preferred over `other`.
+    pub fn prefers(&self, transport: Transport, other: Transport) -> bool {
+        let rank = |t| self.transports().iter().position(|&x| x == t);
+        match (rank(transport), rank(other)) {
+            (Some(rank), Some(other_rank)) => rank < other_rank,
+            (Some(_), None) => true,
+            _ => false,
+        }
+    }
+}
+
+enum ConnectionState<T> {
+    Disconnected,
+    Connected {
+        model: &'static DeviceModel,
+        connection: T,
+    },
+}
+
+/// Platform-neutral connection logic driven by device arrival and removal.
+///
+/// Among the arrived keyboards, it connects to the one over the most preferred transport that the
+/// policy allows, and falls back to another one when the connected one is removed.
+/// `K` identifies a device across its arrival and removal.
+pub struct TransportAgnostic<K, C: Connector> {
+    connector: C,
+    policy: TransportPolicy,
+    registry: &'static Registry,
+    notify_devices: Vec<DeviceInfo>,
+    devices: HashMap<K, &'static DeviceModel>,
+    state: ConnectionState<C::Connection>,
+}
+
+impl<K: Eq + Hash, C: Connector> TransportAgnostic<K, C> {
+    pub fn new(connector: C, policy: TransportPolicy, registry: &'static Registry) -> Self {
+        Self {
+            connector,
+            policy,
+            registry,
+            notify_devices: registry.notify_device_infos(),
+            devices: HashMap::new(),
+            state: ConnectionState::Disconnected,
+        }
+    }
+
+    pub fn connection_mut(&mut self) -> Option<&mut C::Connection> {
+        match &mut self.state {
+            ConnectionState::Connected { connection, .. } => Some(connection),
+            ConnectionState::Disconnected => None,
+        }
+    }
+
+    pub fn model(&self) -> Option<&'static DeviceModel> {
+        match &self.state {
+            ConnectionState::Connected { model, .. } => Some(*model),
+            ConnectionState::Disconnected => None,
+        }
+    }
+
+    /// Returns `false` if the device is not the one to be notified.
+    pub fn arrival(&mut self, key: K, device_info: DeviceInfo) -> bool {
+        let model = self.registry.model_of(&device_info);
+        debug!(
+            "ARRIVAL: {:?}, {:?}",
+            device_info,
+            model.map(|model| model.transport)
+        );
+        if !self.notify_devices.contains(&device_info) {
+            return false;
+        }
+        let model = match model {
+            Some(model) if self.policy.allows(model.transport) => model,
+            _ => {
+                debug!("ARRIVAL: Not allowed by {:?}", self.policy);
+                return false;
+            }
+        };
+
+        self.devices.insert(key, model);
+        debug!("ARRIVAL: OK");
+
+        match self.model() {
+            None => self.try_connect(None),
+            Some(current) if self.policy.prefers(model.transport, current.transport) => {
+                // e.g. The wireless dongle is still connected, but the keyboard is changed to
+                // Bluetooth.
+                self.try_connect_to(&[model]);
+            }
+            _ => {}
+        }
+
+        true
+    }
+
+    pub fn removal(&mut self, key: &K) {
+        if let Some(model) = self.devices.remove(key) {
+            debug!("REMOVAL: {:?}, {:?}", model.name, model.transport);
+
+            if self
+                .model()
+                .is_some_and(|current| std::ptr::eq(current, model))
+            {
+                self.state = ConnectionState::Disconnected;
+                info!("Disconnected: {} over {}", model.name, model.transport);
+                // Other collections of the removed one might not be removed yet.
+                self.try_connect(Some(model));
+            }
+        }
+    }
+
+    /// Tries to connect to the arrived models in the order of the policy.
+    fn try_connect(&mut self, except: Option<&'static DeviceModel>) {
+        let mut candidates: Vec<&'static DeviceModel> = Vec::new();
+        for &transport in self.policy.transports() {
+            for model in self.registry.models() {
+                let arrived = self.devices.values().any(|&x| std::ptr::eq(x, model));
+                let excepted = except.is_some_and(|except| std::ptr::eq(except, model));
+                if model.transport == transport && arrived && !excepted {
+                    candidates.push(model);
+                }
+            }
+        }
+        if !candidates.is_empty() {
+            self.try_connect_to(&candidates);
+        }
+    }
+
+    fn try_connect_to(&mut self, models: &[&'static DeviceModel]) {
+        info!("Connecting");
+        let mut errors = Vec::new();
+        for &model in models {
+            match self.connect(model) {
+                Ok(()) => {
+                    info!("Connected to {} over {}!", model.name, model.transport);
+                    return;
+                }
+                Err(err) => errors.push((model, err)),
+            }
+        }
+
+        for (model, err) in errors {
+            error!(
+                "Cannot connect to {} over {}: {}",
+                model.name, model.transport, err
+            );
+        }
+    }
+
+    fn connect(&mut self, model: &'static DeviceModel) -> Result<()> {
+        let connection = self.connector.connect(model)?;
+        self.state = ConnectionState::Connected { model, connection };
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::hid::{PID_BT, PID_USB};
+
+    /// Records the connection attempts, and fails them over the transports in `failing`.
+    #[derive(Default)]
+    struct FakeConnector {
+        failing: Vec<Transport>,
+        attempts: Vec<Transport>,
+    }
+
+    impl Connector for FakeConnector {
+        type Connection = Transport;
+
+        fn connect(&mut self, model: &'static DeviceModel) -> Result<Self::Connection> {
+            self.attempts.push(model.transport);
+            if self.failing.contains(&model.transport) {
+                bail!("Cannot open {}", model.transport);
+            }
+            Ok(model.transport)
+        }
+    }
+
+    const USB: u32 = 1;
+    const BT: u32 = 2;
+
+    fn transport_agnostic(
+        policy: TransportPolicy,
+        failing: &[Transport],
+    ) -> TransportAgnostic<u32, FakeConnector> {
+        let registry = Box::leak(Box::new(Registry::builtin()));
+        let connector = FakeConnector {
+            failing: failing.to_vec(),
+            ..Default::default()
+        };
+        TransportAgnostic::new(connector, policy, registry)
+    }
+
+    fn middle_button(product_id: u16) -> DeviceInfo {
+        let registry = Registry::builtin();
+        let model = registry
+            .models()
+            .iter()
+            .find(|model| model.product_id == product_id)
+            .unwrap();
+        model.device_info(model.middle_button_collection)
+    }
+
+    fn arrive(ta: &mut TransportAgnostic<u32, FakeConnector>, key: u32) -> bool {
+        let product_id = if key == USB { PID_USB } else { PID_BT };
+        ta.arrival(key, middle_button(product_id))
+    }
+
+    fn connected(ta: &mut TransportAgnostic<u32, FakeConnector>) -> Option<Transport> {
+        let connection = ta.connection_mut().copied();
+        assert_eq!(connection, ta.model().map(|model| model.transport));
+        connection
+    }
+
+    #[test]
+    fn ignores_other_devices() {
+        let mut ta = transport_agnostic(TransportPolicy::Auto, &[]);
+        let mut device_info = middle_button(PID_USB);
+        device_info.usage_page = 0x01;
+        assert!(!ta.arrival(USB, device_info));
+        assert_eq!(connected(&mut ta), None);
+        assert!(ta.connector.attempts.is_empty());
+    }
+
+    #[test]
+    fn auto_switches_to_bt_on_arrival() {
+        let mut ta = transport_agnostic(TransportPolicy::Auto, &[]);
+        assert!(arrive(&mut ta, USB));
+        assert_eq!(connected(&mut ta), Some(Transport::USB));
+        assert!(arrive(&mut ta, BT));
+        assert_eq!(connected(&mut ta), Some(Transport::BT));
+
+        ta.removal(&BT);
+        assert_eq!(connected(&mut ta), Some(Transport::USB));
+        ta.removal(&USB);
+        assert_eq!(connected(&mut ta), None);
+        assert_eq!(
+            ta.connector.attempts,
+            [Transport::USB, Transport::BT, Transport::USB]
+        );
+    }
+
+    #[test]
+    fn prefer_usb_keeps_usb_on_arrival() {
+        let mut ta = transport_agnostic(TransportPolicy::PreferUSB, &[]);
+        assert!(arrive(&mut ta, BT));
+        assert_eq!(connected(&mut ta), Some(Transport::BT));
+        assert!(arrive(&mut ta, USB));
+        assert_eq!(connected(&mut ta), Some(Transport::USB));
+        assert!(arrive(&mut ta, BT));
+        assert_eq!(connected(&mut ta), Some(Transport::USB));
+
+        // Removing the unused one keeps the connection.
+        ta.removal(&BT);
+        assert_eq!(connected(&mut ta), Some(Transport::USB));
+        ta.removal(&USB);
+        assert_eq!(connected(&mut ta), None);
+        assert_eq!(ta.connector.attempts, [Transport::BT, Transport::USB]);
+    }
+
+    #[test]
+    fn usb_only_ignores_bt() {
+        let mut ta = transport_agnostic(TransportPolicy::USBOnly, &[]);
+        assert!(!arrive(&mut ta, BT));
+        assert_eq!(connected(&mut ta), None);
+        assert!(arrive(&mut ta, USB));
+        assert_eq!(connected(&mut ta), Some(Transport::USB));
+
+        ta.removal(&BT);
+        assert_eq!(connected(&mut ta), Some(Transport::USB));
+        ta.removal(&USB);
+        assert_eq!(connected(&mut ta), None);
+        assert_eq!(ta.connector.attempts, [Transport::USB]);
+    }
+
+    #[test]
+    fn bt_only_ignores_usb() {
+        let mut ta = transport_agnostic(TransportPolicy::BTOnly, &[]);
+        assert!(!arrive(&mut ta, USB));
+        assert_eq!(connected(&mut ta), None);
+        assert!(arrive(&mut ta, BT));
+        assert_eq!(connected(&mut ta), Some(Transport::BT));
+
+        ta.removal(&BT);
+        assert_eq!(connected(&mut ta), None);
+        assert_eq!(ta.connector.attempts, [Transport::BT]);
+    }
+
+    #[test]
+    fn fails_over_from_usb_to_bt() {
+        let mut ta = transport_agnostic(TransportPolicy::PreferUSB, &[Transport::USB]);
+        assert!(arrive(&mut ta, USB));
+        assert_eq!(connected(&mut ta), None);
+        assert!(arrive(&mut ta, BT));
+        assert_eq!(connected(&mut ta), Some(Transport::BT));
+        assert_eq!(
+            ta.connector.attempts,
+            [Transport::USB, Transport::USB, Transport::BT]
+        );
+
+        // USB is tried again when the connected one is removed.
+        ta.removal(&BT);
+        assert_eq!(connected(&mut ta), None);
+        assert_eq!(ta.connector.attempts.last(), Some(&Transport::USB));
+    }
+}
@@ -1,4 +1,4 @@
-use std::collections::HashMap;
+use std::sync::Arc;
 
 use anyhow::*;
 use log::*;
@@ -9,105 +9,87 @@ use winapi::um::winuser::{GIDC_ARRIVAL, GIDC_REMOVAL, WM_INPUT_DEVICE_CHANGE};
 
 use crate::args::Args;
 use crate::bt_wheel_blocker::WheelBlocker;
+use crate::control::ScrollConfig;
 use crate::hid;
-use crate::hid::{DeviceInfo, Transport, PID_BT, VID_LENOVO};
-use crate::input::get_hid_device_info;
-use crate::tpmiddle::TPMiddle;
+use crate::hid::Transport;
+use crate::input::{get_hid_device_info, SendInputSink};
+use crate::output::OutputSink;
+use crate::raw_input_tpmiddle::RawInputTPMiddle;
+use crate::registry::{DeviceModel, Registry};
+use crate::restore;
+use crate::transport_agnostic::{Connector, TransportAgnostic};
 use crate::window::{WindowProc, WindowProcError, WindowProcResult};
 
-enum ConnectionState {
-    Disconnected,
+enum Connection {
     USB {
-        tpmiddle: TPMiddle,
+        tpmiddle: RawInputTPMiddle,
     },
     BT {
         wheel_blocker: WheelBlocker,
-        tpmiddle: TPMiddle,
+        tpmiddle: RawInputTPMiddle,
     },
 }
 
-pub struct TransportAgnosticTPMiddle<'a> {
+struct RawInputConnector {
     args: Args,
-    notify_devices: &'a [DeviceInfo],
-    devices: HashMap<HANDLE, DeviceInfo>,
-    state: ConnectionState,
+    scroll: ScrollConfig,
 }
 
-impl<'a> TransportAgnosticTPMiddle<'a> {
-    pub fn new(args: Args, notify_devices: &'a [DeviceInfo]) -> Self {
-        Self {
-            args,
-            notify_devices,
-            devices: HashMap::new(),
-            state: ConnectionState::Disconnected,
-        }
-    }
-
-    fn try_connect_bt_then_usb(&mut self) {
-        info!("Connecting");
-        let bt_err = match self.connect_over(Transport::BT) {
-            Ok(()) => {
-                info!("Connected over {}!", Transport::BT);
-                return;
-            }
-            Err(err) => err,
-        };
-
-        match self.connect_over(Transport::USB) {
-            Ok(()) => {
-                info!("Connected over {}!", Transport::USB);
-            }
-            Err(err) => {
-                error!("Cannot connect over Bluetooth: {}", bt_err);
-                error!("Cannot connect over USB: {}", err);
-            }
-        }
-    }
-
-    fn try_connect_over(&mut self, transport: Transport) {
-        info!("Connecting over {}", transport);
-        match self.connect_over(transport) {
-            Ok(()) => {
-                info!("Connected!");
-            }
-            Err(err) => {
-                error!("Error: {}", err);
-            }
-        }
-    }
-
-    fn connect_over(&mut self, transport: Transport) -> Result<()> {
-        hid::initialize_keyboard(transport, self.args.sensitivity, self.args.fn_lock())?;
-
-        self.state = match transport {
-            Transport::USB => {
-                let tpmiddle =
-                    TPMiddle::new(transport.device_info(), self.args.scroll.create_control());
-                ConnectionState::USB { tpmiddle }
-            }
+impl Connector for RawInputConnector {
+    type Connection = Connection;
+
+    fn connect(&mut self, model: &'static DeviceModel) -> Result<Connection> {
+        hid::initialize_keyboard(model, self.args.sensitivity, self.args.fn_lock())?;
+        restore::register(model);
+
+        let output: Arc<dyn OutputSink> = Arc::new(SendInputSink);
+        let tpmiddle = RawInputTPMiddle::new(
+            model,
+            self.scroll.create_tpmiddle(),
+            self.scroll.create_control(output.clone()),
+            output,
+        );
+        let connection = match model.transport {
+            Transport::USB => Connection::USB { tpmiddle },
             Transport::BT => {
-                let wheel_blocker = WheelBlocker::new(VID_LENOVO, PID_BT)?;
-                let tpmiddle =
-                    TPMiddle::new(transport.device_info(), self.args.scroll.create_control());
-                ConnectionState::BT {
+                let wheel_blocker = WheelBlocker::new(model.vendor_id, model.product_id)?;
+                Connection::BT {
                     wheel_blocker,
                     tpmiddle,
                 }
             }
         };
 
-        Ok(())
+        Ok(connection)
+    }
+}
+
+pub struct TransportAgnosticTPMiddle {
+    transport_agnostic: TransportAgnostic<HANDLE, RawInputConnector>,
+}
+
+impl TransportAgnosticTPMiddle {
+    pub fn new(args: Args, scroll: ScrollConfig, registry: &'static Registry) -> Self {
+        let policy = args.transport;
+        Self {
+            transport_agnostic: TransportAgnostic::new(
+                RawInputConnector { args, scroll },
+                policy,
+                registry,
+            ),
+        }
     }
 
     fn on_mouse_device_change(&mut self) -> Result<()> {
-        if let ConnectionState::BT { wheel_blocker, .. } = &mut self.state {
+        if let Some(Connection::BT { wheel_blocker, .. }) = self.transport_agnostic.connection_mut()
+        {
             wheel_blocker.rescan_target_device_handle()?;
         }
         Ok(())
     }
 }
 
-impl<'a> WindowProc for TransportAgnosticTPMiddle<'a> {
+impl WindowProc for TransportAgnosticTPMiddle {
     fn proc(
         &mut self,
         hwnd: HWND,
@@ -122,22 +104,7 @@ impl<'a> WindowProc for TransportAgnosticTPMiddle<'a> {
 
                 match get_hid_device_info(handle) {
                     Ok(Some(device_info)) => {
-                        debug!("ARRIVAL: {:?}, {:?}", device_info, device_info.transport());
-                        if !self.notify_devices.iter().any(|x| x == &device_info) {
-                            return Ok(0);
-                        }
-
-                        self.devices.insert(handle, device_info);
-                        debug!("ARRIVAL: OK");
-
-                        if let ConnectionState::Disconnected = self.state {
-                            self.try_connect_bt_then_usb();
-                        } else if matches!(self.state, ConnectionState::USB{..})
-                            && device_info.transport() == Some(Transport::BT)
-                        {
-                            // The wireless dongle is still connected, but the keyboard is changed to Bluetooth.
-                            self.try_connect_over(Transport::BT);
-                        }
+                        self.transport_agnostic.arrival(handle, device_info);
                     }
                     Ok(None) => {
                         self.on_mouse_device_change()?;
@@ -154,36 +121,20 @@ impl<'a> WindowProc for TransportAgnosticTPMiddle<'a> {
             WM_INPUT_DEVICE_CHANGE if w_param as DWORD == GIDC_REMOVAL => {
                 let handle = l_param as _;
                 trace!("REMOVAL: {:?}", handle);
-                if let Some(device_info) = self.devices.remove(&handle) {
-                    debug!("REMOVAL: {:?}, {:?}", device_info, device_info.transport());
-
-                    match (device_info.transport(), &self.state) {
-                        (Some(Transport::BT), ConnectionState::BT { .. }) => {
-                            self.state = ConnectionState::Disconnected;
-                            info!("Disconnected: Bluetooth");
-                            self.try_connect_over(Transport::USB);
-                        }
-                        (Some(Transport::USB), ConnectionState::USB { .. }) => {
-                            self.state = ConnectionState::Disconnected;
-                            info!("Disconnected: USB");
-                            self.try_connect_over(Transport::BT);
-                        }
-                        _ => {}
-                    }
-                }
+                self.transport_agnostic.removal(&handle);
 
                 Ok(0)
             }
-            _ => match &mut self.state {
-                ConnectionState::USB { tpmiddle } => tpmiddle.proc(hwnd, u_msg, w_param, l_param),
-                ConnectionState::BT {
+            _ => match self.transport_agnostic.connection_mut() {
+                Some(Connection::USB { tpmiddle }) => tpmiddle.proc(hwnd, u_msg, w_param, l_param),
+                Some(Connection::BT {
                     wheel_blocker,
                     tpmiddle,
-                } => {
+                }) => {
                     wheel_blocker.peek_message(u_msg, l_param);
                     tpmiddle.proc(hwnd, u_msg, w_param, l_param)
                 }
-                _ => Err(WindowProcError::UnhandledMessage),
+                None => Err(WindowProcError::UnhandledMessage),
             },
         }
     }
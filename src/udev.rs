@@ -0,0 +1,146 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::Read;
+use std::os::unix::io::{AsRawFd, FromRawFd, RawFd};
+use std::path::PathBuf;
+
+use anyhow::*;
+use log::*;
+
+/// Multicast group of the events processed by udevd.
+/// Device nodes have proper permissions when the events are received.
+const UDEV_MONITOR_UDEV: u32 = 2;
+
+const UDEV_MONITOR_MAGIC: u32 = 0xFEED_CAFE;
+
+#[derive(Eq, PartialEq, Copy, Clone, Debug)]
+pub enum UdevAction {
+    Add,
+    Remove,
+    Other,
+}
+
+#[derive(Debug)]
+pub struct UdevEvent {
+    pub action: UdevAction,
+    properties: HashMap<String, String>,
+}
+
+impl UdevEvent {
+    pub fn property(&self, key: &str) -> Option<&str> {
+        self.properties.get(key).map(String::as_str)
+    }
+
+    pub fn subsystem(&self) -> Option<&str> {
+        self.property("SUBSYSTEM")
+    }
+
+    pub fn syspath(&self) -> Option<PathBuf> {
+        let devpath = self.property("DEVPATH")?;
+        Some(PathBuf::from(format!("/sys{}", devpath)))
+    }
+}
+
+/// Receives device events from udevd over netlink, like `udevadm monitor --udev`.
+pub struct UdevMonitor {
+    socket: File,
+    buffer: Vec<u8>,
+}
+
+impl UdevMonitor {
+    pub fn new() -> Result<Self> {
+        let fd = unsafe {
+            libc::socket(
+                libc::AF_NETLINK,
+                libc::SOCK_RAW | libc::SOCK_CLOEXEC | libc::SOCK_NONBLOCK,
+                libc::NETLINK_KOBJECT_UEVENT,
+            )
+        };
+        if fd < 0 {
+            return Err(std::io::Error::last_os_error()).context("creating a netlink socket");
+        }
+        let socket = unsafe { File::from_raw_fd(fd) };
+
+        let mut addr: libc::sockaddr_nl = unsafe { std::mem::zeroed() };
+        addr.nl_family = libc::AF_NETLINK as _;
+        addr.nl_groups = UDEV_MONITOR_UDEV;
+        let result = unsafe {
+            libc::bind(
+                fd,
+                &addr as *const libc::sockaddr_nl as *const libc::sockaddr,
+                std::mem::size_of_val(&addr) as _,
+            )
+        };
+        if result < 0 {
+            return Err(std::io::Error::last_os_error()).context("binding a netlink socket");
+        }
+
+        const BUFFER_SIZE: usize = 8192;
+        Ok(Self {
+            socket,
+            buffer: vec![0; BUFFER_SIZE],
+        })
+    }
+
+    /// Receives a pending event without blocking.
+    pub fn receive(&mut self) -> Result<Option<UdevEvent>> {
+        loop {
+            let size = match self.socket.read(&mut self.buffer) {
+                Ok(size) => size,
+                Err(err) if err.kind() == std::io::ErrorKind::WouldBlock => return Ok(None),
+                Err(err) if err.kind() == std::io::ErrorKind::Interrupted => continue,
+                Err(err) => return Err(err.into()),
+            };
+
+            match parse_message(&self.buffer[..size]) {
+                Some(event) => return Ok(Some(event)),
+                None => {
+                    trace!("Unexpected udev message");
+                    continue;
+                }
+            }
+        }
+    }
+}
+
+impl AsRawFd for UdevMonitor {
+    fn as_raw_fd(&self) -> RawFd {
+        self.socket.as_raw_fd()
+    }
+}
+
+/// Parses a message that consists of the header of libudev and `KEY=VALUE\0` properties.
+fn parse_message(message: &[u8]) -> Option<UdevEvent> {
+    fn read_u32(message: &[u8], offset: usize) -> Option<u32> {
+        let bytes = message.get(offset..offset + 4)?;
+        Some(u32::from_ne_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    if !message.starts_with(b"libudev\0") {
+        return None;
+    }
+    let magic = u32::from_be(read_u32(message, 8)?);
+    if magic != UDEV_MONITOR_MAGIC {
+        return None;
+    }
+    let properties_off = read_u32(message, 16)? as usize;
+    let properties_len = read_u32(message, 20)? as usize;
+    let properties = message.get(properties_off..properties_off + properties_len)?;
+
+    let properties: HashMap<String, String> = properties
+        .split(|byte| *byte == 0)
+        .filter_map(|property| {
+            let property = std::str::from_utf8(property).ok()?;
+            let mut split = property.splitn(2, '=');
+            Some((split.next()?.to_owned(), split.next()?.to_owned()))
+        })
+        .collect();
+
+    let action = match properties.get("ACTION").map(String::as_str) {
+        Some("add") => UdevAction::Add,
+        Some("remove") => UdevAction::Remove,
+        _ => UdevAction::Other,
+    };
+
+    Some(UdevEvent { action, properties })
+}
@@ -0,0 +1,265 @@
+use std::os::unix::io::AsRawFd;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use anyhow::*;
+use log::*;
+
+use crate::args::Args;
+use crate::control::ScrollConfig;
+use crate::evdev_wheel_blocker::{self, WheelBlocker};
+use crate::hid;
+use crate::hid::Transport;
+use crate::hidraw_tpmiddle::HidrawTPMiddle;
+use crate::input::hidraw::{self, HidrawNode};
+use crate::input::keyboard::{self, ModifierReader};
+use crate::input::uinput::VirtualPointer;
+use crate::output::OutputSink;
+use crate::registry::{DeviceModel, Registry};
+use crate::restore;
+use crate::transport_agnostic::{Connector, TransportAgnostic};
+use crate::udev::{UdevAction, UdevEvent, UdevMonitor};
+use crate::util::poll;
+
+struct Connection {
+    tpmiddle: HidrawTPMiddle,
+    /// Always over BT, which emits native wheel events. Over USB, only to capture the pointer
+    /// motion for the sticky scroll, which is disabled if it cannot be created.
+    wheel_blocker: Option<WheelBlocker>,
+}
+
+struct HidrawConnector {
+    args: Args,
+    scroll: ScrollConfig,
+    pointer: Arc<VirtualPointer>,
+}
+
+impl Connector for HidrawConnector {
+    type Connection = Connection;
+
+    fn connect(&mut self, model: &'static DeviceModel) -> Result<Connection> {
+        hid::initialize_keyboard(model, self.args.sensitivity, self.args.fn_lock())?;
+        restore::register(model);
+
+        let new_wheel_blocker =
+            || WheelBlocker::new(model.vendor_id, model.product_id, self.pointer.clone());
+        let wheel_blocker = if model.transport == Transport::BT {
+            Some(new_wheel_blocker()?)
+        } else if self.scroll.sticky.is_some() {
+            match new_wheel_blocker() {
+                Ok(wheel_blocker) => {
+                    if !wheel_blocker.is_blocking() {
+                        warn!("Sticky scroll falls back to the click until the mouse node arrives");
+                    }
+                    Some(wheel_blocker)
+                }
+                Err(err) => {
+                    warn!(
+                        "Sticky scroll is disabled: cannot capture the pointer motion: {:?}",
+                        err
+                    );
+                    None
+                }
+            }
+        } else {
+            None
+        };
+        let tpmiddle = if wheel_blocker.is_some() {
+            self.scroll.create_tpmiddle()
+        } else {
+            let scroll = ScrollConfig {
+                sticky: None,
+                ..self.scroll.clone()
+            };
+            scroll.create_tpmiddle()
+        };
+
+        let output: Arc<dyn OutputSink> = self.pointer.clone();
+        let tpmiddle = HidrawTPMiddle::new(
+            model,
+            tpmiddle,
+            self.scroll.create_control(output.clone()),
+            output,
+        )?;
+
+        Ok(Connection {
+            tpmiddle,
+            wheel_blocker,
+        })
+    }
+}
+
+/// Connects to the keyboard on the arrival and removal of hidraw nodes that udev notifies.
+/// Counterpart of `TransportAgnosticTPMiddle` of Windows.
+pub struct UdevTPMiddle {
+    monitor: UdevMonitor,
+    registry: &'static Registry,
+    transport_agnostic: TransportAgnostic<PathBuf, HidrawConnector>,
+    modifiers: ModifierReader,
+}
+
+impl UdevTPMiddle {
+    pub fn new(args: Args, scroll: ScrollConfig, registry: &'static Registry) -> Result<Self> {
+        let pointer = Arc::new(VirtualPointer::new("tpmiddle-rs")?);
+        // Start monitoring before enumerating not to miss devices in between.
+        let monitor = UdevMonitor::new()?;
+        let policy = args.transport;
+        let mut this = Self {
+            monitor,
+            registry,
+            transport_agnostic: TransportAgnostic::new(
+                HidrawConnector {
+                    args,
+                    scroll,
+                    pointer,
+                },
+                policy,
+                registry,
+            ),
+            modifiers: ModifierReader::new()?,
+        };
+
+        for node in hidraw::enumerate()? {
+            this.on_hidraw_arrival(node);
+        }
+
+        Ok(this)
+    }
+
+    pub fn run(&mut self) -> Result<()> {
+        loop {
+            let mut fds = vec![libc::pollfd {
+                fd: self.monitor.as_raw_fd(),
+                events: libc::POLLIN,
+                revents: 0,
+            }];
+            let mut num_tpmiddle_fds = 0;
+            let mut deadline = None;
+            if let Some(connection) = self.transport_agnostic.connection_mut() {
+                fds.extend(connection.tpmiddle.pollfds());
+                num_tpmiddle_fds = fds.len() - 1;
+                if let Some(wheel_blocker) = &connection.wheel_blocker {
+                    fds.extend(wheel_blocker.pollfds());
+                }
+                deadline = connection.tpmiddle.deadline();
+            }
+
+            let timeout = deadline.map(|deadline| {
+                // Round up not to wake up right before the deadline.
+                deadline.saturating_duration_since(Instant::now()) + Duration::from_millis(1)
+            });
+            if !poll(&mut fds, timeout)? {
+                continue;
+            }
+
+            let (tpmiddle_fds, wheel_blocker_fds) = fds[1..].split_at(num_tpmiddle_fds);
+            if let Some(connection) = self.transport_agnostic.connection_mut() {
+                let tpmiddle = &mut connection.tpmiddle;
+                if let Some(wheel_blocker) = &mut connection.wheel_blocker {
+                    let motion = wheel_blocker.process_polled(wheel_blocker_fds)?;
+                    tpmiddle.process_events(motion, &mut self.modifiers);
+                    // The motion cannot be captured without the mouse node.
+                    tpmiddle.set_sticky_available(wheel_blocker.is_blocking());
+                }
+                tpmiddle.process_polled(tpmiddle_fds, &mut self.modifiers)?;
+                tpmiddle.process_timeout();
+                if let Some(wheel_blocker) = &mut connection.wheel_blocker {
+                    wheel_blocker.set_capture_motion(tpmiddle.captures_motion());
+                }
+            }
+
+            if fds[0].revents & libc::POLLIN != 0 {
+                while let Some(event) = self.monitor.receive()? {
+                    self.on_udev_event(event);
+                }
+            }
+        }
+    }
+
+    fn on_udev_event(&mut self, event: UdevEvent) {
+        let syspath = if let Some(syspath) = event.syspath() {
+            syspath
+        } else {
+            return;
+        };
+        match event.subsystem() {
+            Some("hidraw") => self.on_hidraw_event(event.action, syspath),
+            Some("input") if event.action == UdevAction::Add => {
+                if let Err(err) = self.on_input_device_arrival(&syspath) {
+                    debug!("Error while rescanning input devices: {}", err);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn on_input_device_arrival(&mut self, syspath: &Path) -> Result<()> {
+        if keyboard::is_keyboard(syspath)? {
+            // Not a target of the wheel blocker, which isn't a keyboard.
+            return self.modifiers.rescan();
+        }
+
+        let model = match self.transport_agnostic.model() {
+            Some(model) => model,
+            None => return Ok(()),
+        };
+        if !evdev_wheel_blocker::is_target(syspath, model.vendor_id, model.product_id)? {
+            return Ok(());
+        }
+        if let Some(Connection {
+            wheel_blocker: Some(wheel_blocker),
+            ..
+        }) = self.transport_agnostic.connection_mut()
+        {
+            wheel_blocker.rescan_target_devices()?;
+        }
+        Ok(())
+    }
+
+    fn on_hidraw_event(&mut self, action: UdevAction, syspath: PathBuf) {
+        match action {
+            UdevAction::Add => {
+                trace!("ARRIVAL: {:?}", syspath);
+                match HidrawNode::from_syspath(&syspath) {
+                    Ok(node) => self.on_hidraw_arrival(node),
+                    Err(err) => {
+                        // The device is spuriously disconnected before handling this event
+                        debug!("Error while get device info: {}", err);
+                    }
+                }
+            }
+            UdevAction::Remove => {
+                trace!("REMOVAL: {:?}", syspath);
+                self.transport_agnostic.removal(&syspath);
+            }
+            UdevAction::Other => {}
+        }
+    }
+
+    fn on_hidraw_arrival(&mut self, node: HidrawNode) {
+        let current = self.transport_agnostic.model();
+        for device_info in node.device_infos {
+            if !self
+                .transport_agnostic
+                .arrival(node.syspath.clone(), device_info)
+            {
+                continue;
+            }
+
+            let is_connected_model = match (current, self.registry.model_of(&device_info)) {
+                (Some(current), Some(model)) => std::ptr::eq(current, model),
+                _ => false,
+            };
+            if is_connected_model {
+                // Another node of the connected keyboard.
+                if let Some(connection) = self.transport_agnostic.connection_mut() {
+                    if let Err(err) = connection.tpmiddle.rescan() {
+                        warn!("Cannot rescan hidraw nodes: {}", err);
+                    }
+                }
+            }
+            break;
+        }
+    }
+}
@@ -1,3 +1,4 @@
+#[cfg(windows)]
 use std::ops::{Deref, DerefMut};
 
 #[macro_export]
@@ -72,17 +73,22 @@ macro_rules! c_try_ne {
     };
 }
 
+#[cfg(windows)]
 pub struct ForceSendSync<T>(T);
 
+#[cfg(windows)]
 unsafe impl<T> Send for ForceSendSync<T> {}
+#[cfg(windows)]
 unsafe impl<T> Sync for ForceSendSync<T> {}
 
+#[cfg(windows)]
 impl<T> ForceSendSync<T> {
     pub fn new(value: T) -> Self {
         Self(value)
     }
 }
 
+#[cfg(windows)]
 impl<T> Deref for ForceSendSync<T> {
     type Target = T;
 
@@ -91,8 +97,53 @@ impl<T> Deref for ForceSendSync<T> {
     }
 }
 
+#[cfg(windows)]
 impl<T> DerefMut for ForceSendSync<T> {
     fn deref_mut(&mut self) -> &mut Self::Target {
         &mut self.0
     }
 }
+
+/// Waits for the events of `fds` up to `timeout`, or indefinitely if `None`.
+/// Returns `false` if it is interrupted by a signal.
+#[cfg(target_os = "linux")]
+pub fn poll(
+    fds: &mut [libc::pollfd],
+    timeout: Option<std::time::Duration>,
+) -> anyhow::Result<bool> {
+    let timeout = timeout.map_or(-1, |timeout| timeout.as_millis() as libc::c_int);
+    let result = unsafe { libc::poll(fds.as_mut_ptr(), fds.len() as _, timeout) };
+    if result < 0 {
+        let err = std::io::Error::last_os_error();
+        if err.kind() == std::io::ErrorKind::Interrupted {
+            return Ok(false);
+        }
+        return Err(err.into());
+    }
+    Ok(true)
+}
+
+#[cfg(target_os = "linux")]
+pub const SYSFS_INPUT: &str = "/sys/class/input";
+
+/// Tests if `syspath` of [`SYSFS_INPUT`] is an event node, like `event3`, rather than `input3` or
+/// `mouse0`.
+#[cfg(target_os = "linux")]
+pub fn is_event_node(syspath: &std::path::Path) -> bool {
+    syspath
+        .file_name()
+        .and_then(|name| name.to_str())
+        .is_some_and(|name| name.starts_with("event"))
+}
+
+/// Tests a bit of the capability bitmask of sysfs, which is hex `long`s from the most significant.
+#[cfg(target_os = "linux")]
+pub fn has_bit(bitmask: &str, bit: usize) -> bool {
+    const LONG_BITS: usize = std::mem::size_of::<libc::c_long>() * 8;
+    bitmask
+        .split_whitespace()
+        .rev()
+        .nth(bit / LONG_BITS)
+        .and_then(|word| u64::from_str_radix(word, 16).ok())
+        .is_some_and(|word| word & (1 << (bit % LONG_BITS)) != 0)
+}
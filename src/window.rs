@@ -18,12 +18,18 @@ use winapi::um::wincon::GetConsoleWindow;
 use winapi::um::winuser::{
     CreateWindowExW, DefWindowProcW, DestroyWindow, DispatchMessageW, GetMessageW,
     GetWindowLongPtrW, IsWindowVisible, PostQuitMessage, RegisterClassExW, RegisterRawInputDevices,
-    SetWindowLongPtrW, ShowWindow, TranslateMessage, UnregisterClassW, HWND_MESSAGE, MSG,
-    RAWINPUTDEVICE, RIDEV_DEVNOTIFY, RIDEV_INPUTSINK, RIDEV_REMOVE, SW_HIDE, WNDCLASSEXW,
+    SetWindowLongPtrW, ShowWindow, TranslateMessage, UnregisterClassW, MSG, RAWINPUTDEVICE,
+    RIDEV_DEVNOTIFY, RIDEV_INPUTSINK, RIDEV_REMOVE, SW_HIDE, WM_CLOSE, WM_ENDSESSION,
+    WM_QUERYENDSESSION, WNDCLASSEXW,
 };
 
 use crate::hid::DeviceInfo;
+use crate::restore;
 
+/// A hidden top-level window.
+///
+/// It isn't a message-only window, since those don't receive `WM_QUERYENDSESSION` and
+/// `WM_ENDSESSION`, which are the only notice of logoff and shutdown for a process with windows.
 pub struct Window<T> {
     _class: WindowClass<T>,
     pub hwnd: HWND,
@@ -45,7 +51,7 @@ impl<T: WindowProc> Window<T> {
             0,
             0,
             0,
-            HWND_MESSAGE,
+            NULL as _,
             NULL as _,
             NULL as _,
             proc.as_mut() as *mut T as _,
@@ -137,6 +143,22 @@ extern "system" fn window_proc<T: WindowProc>(
         }
     };
     assert!(!this.is_null());
+    match u_msg {
+        WM_QUERYENDSESSION => return TRUE as LRESULT,
+        WM_ENDSESSION => {
+            // The process may be terminated any time after returning.
+            if w_param != FALSE as WPARAM {
+                restore::restore_keyboards();
+            }
+            return 0;
+        }
+        WM_CLOSE => {
+            // The keyboards are restored after the message loop.
+            unsafe { PostQuitMessage(0) };
+            return 0;
+        }
+        _ => {}
+    }
     unsafe {
         match (*this).proc(hwnd, u_msg, w_param, l_param) {
             Ok(result) => result,